@@ -0,0 +1,163 @@
+//! Compares encoding packets inline with moving every packet to the blocking thread pool,
+//! measures connection throughput for different offload thresholds
+//! and broadcasting with and without preparing the packet.
+//! Run with `cargo bench --features p47`.
+use bytes::BytesMut;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use minceraft::net::{
+    codec::PacketCodec,
+    conn::Conn,
+    packet::{Packet, PreparedPacket, RawPacket},
+    types::VarInt,
+};
+use minceraft::p47::play::serverbound::KeepAlive;
+use std::sync::Arc;
+use tokio::{runtime::Runtime, sync::Mutex};
+
+const THRESHOLD: i32 = 256;
+const PACKETS: usize = 1000;
+const PLAYERS: usize = 100;
+
+fn keep_alive() -> KeepAlive {
+    KeepAlive { id: 1337 }
+}
+
+/// A packet big enough to be compressed, roughly the size of a chunk column.
+fn big_packet() -> RawPacket {
+    RawPacket {
+        id: VarInt(0x21),
+        data: (0..256 * 1024).map(|i| (i % 251) as u8).collect(),
+    }
+}
+
+fn encode(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut codec = PacketCodec::new(THRESHOLD);
+
+    let mut group = c.benchmark_group("encode keep alive");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("inline", |b| {
+        b.iter(|| {
+            let mut frame = BytesMut::new();
+            codec.encode_frame(&keep_alive().encode().unwrap(), &mut frame).unwrap();
+            frame
+        })
+    });
+    group.bench_function("spawn_blocking", |b| {
+        b.to_async(&rt).iter(|| async move {
+            tokio::task::spawn_blocking(move || {
+                let mut codec = PacketCodec::new(THRESHOLD);
+                let mut frame = BytesMut::new();
+                codec.encode_frame(&keep_alive().encode().unwrap(), &mut frame).unwrap();
+                frame
+            })
+            .await
+            .unwrap()
+        })
+    });
+    group.finish();
+}
+
+/// Sends `packets` through an in-memory connection pair and reads them back.
+async fn round_trip(packets: Vec<RawPacket>, offload_threshold: usize) {
+    let (mut a, mut b) = Conn::pair();
+    for conn in [&mut a, &mut b] {
+        conn.set_compression_threshhold(THRESHOLD);
+        conn.set_offload_threshold(offload_threshold);
+    }
+
+    let count = packets.len();
+    let writer = tokio::spawn(async move {
+        for packet in packets {
+            a.send_raw_packet(packet).await.unwrap();
+        }
+    });
+    for _ in 0..count {
+        b.read_packet().await.unwrap();
+    }
+    writer.await.unwrap();
+}
+
+fn throughput(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("conn keep alives");
+    group.throughput(Throughput::Elements(PACKETS as u64));
+    for (name, offload_threshold) in [("inline", usize::MAX), ("offload everything", 0)] {
+        group.bench_function(name, |b| {
+            b.to_async(&rt).iter_batched(
+                || (0..PACKETS).map(|_| keep_alive().encode().unwrap()).collect(),
+                |packets| round_trip(packets, offload_threshold),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("conn big packets");
+    group.throughput(Throughput::Bytes(big_packet().data.len() as u64 * 16));
+    for (name, offload_threshold) in [("inline", usize::MAX), ("offload", 0)] {
+        group.bench_function(name, |b| {
+            b.to_async(&rt).iter_batched(
+                || (0..16).map(|_| big_packet()).collect(),
+                |packets| round_trip(packets, offload_threshold),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+/// A packet just big enough to be compressed, sent to every player at once.
+fn broadcast_packet() -> RawPacket {
+    RawPacket {
+        id: VarInt(0x15),
+        data: (0..THRESHOLD as usize * 2).map(|i| (i % 7) as u8).collect(),
+    }
+}
+
+/// Connections that throw away everything they send.
+fn players() -> Vec<Conn<tokio::io::Empty, tokio::io::Sink>> {
+    (0..PLAYERS)
+        .map(|i| {
+            let mut conn = Conn::from_halves(tokio::io::empty(), tokio::io::sink());
+            conn.set_compression_threshhold(THRESHOLD);
+            conn.enable_encryption(&[i as u8; 16]).unwrap();
+            conn
+        })
+        .collect()
+}
+
+fn broadcast(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    // Kept between iterations so every compressor is already warmed up
+    let players = Arc::new(Mutex::new(players()));
+
+    let mut group = c.benchmark_group("broadcast");
+    group.throughput(Throughput::Elements(PLAYERS as u64));
+    group.bench_function("encode per player", |b| {
+        b.to_async(&rt).iter(|| async {
+            let packet = broadcast_packet();
+            for conn in players.lock().await.iter_mut() {
+                conn.send_raw_packet(RawPacket {
+                    id: packet.id,
+                    data: packet.data.clone(),
+                })
+                .await
+                .unwrap();
+            }
+        })
+    });
+    group.bench_function("prepared", |b| {
+        b.to_async(&rt).iter(|| async {
+            let packet = PreparedPacket::new(&broadcast_packet(), THRESHOLD).unwrap();
+            for conn in players.lock().await.iter_mut() {
+                conn.send_prepared(&packet).await.unwrap();
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, encode, throughput, broadcast);
+criterion_main!(benches);
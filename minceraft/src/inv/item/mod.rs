@@ -18,6 +18,7 @@ pub trait Item: Sized + Debug {
 pub struct Itemstack<T: Item, U: Enchant> {
     pub item: T,
     pub count: i8,
+    pub damage: i16,
     pub meta: Option<ItemStackMetaData<U>>,
 }
 
@@ -27,6 +28,7 @@ impl<T: Item, U: Enchant> Itemstack<T, U> {
         Ok(Self {
             item,
             count,
+            damage: 0,
             meta: None,
         })
     }
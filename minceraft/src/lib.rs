@@ -5,4 +5,4 @@ pub mod inv;
 #[cfg(feature = "net")]
 pub mod net;
 #[cfg(feature = "p47")]
-mod p47;
+pub mod p47;
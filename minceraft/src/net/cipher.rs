@@ -0,0 +1,201 @@
+//! Stream adapters for the AES/CFB8 encryption that is enabled during login.
+//! ```ignore
+//! let mut writer = CipherWriter::new(stream);
+//! writer.enable_encryption(&shared_secret)?;
+//! writer.write_all(b"encrypted").await?;
+//! writer.flush().await?;
+//! ```
+use super::{Error, Result};
+use aes::Aes128;
+use bytes::{Buf, BytesMut};
+use cfb8::{
+    cipher::{AsyncStreamCipher, NewCipher},
+    Cfb8,
+};
+use futures::ready;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::{self, AsyncRead, AsyncWrite, ReadBuf};
+
+/// How much [`CipherWriter`] buffers from `poll_write` before it writes to the stream on its own.
+pub const WRITE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The cipher of one direction of a connection.
+pub type Cipher = Cfb8<Aes128>;
+
+/// Creates the cipher for the shared secret `key`, which is also used as the iv.
+pub fn new_cipher(key: &[u8]) -> Result<Cipher> {
+    Cfb8::<Aes128>::new_from_slices(key, key).map_err(|e| Error::Cipher(e.to_string()))
+}
+
+/// Decrypts everything that is read from `R` once encryption is enabled.
+pub struct CipherReader<R> {
+    inner: R,
+    cipher: Option<Cipher>,
+}
+
+impl<R> CipherReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            cipher: None,
+        }
+    }
+
+    /// Decrypts everything that is read from now on.
+    pub fn enable_encryption(&mut self, key: &[u8]) -> Result<()> {
+        self.cipher = Some(new_cipher(key)?);
+        Ok(())
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// Decrypts bytes that were read from the stream but kept somewhere else, e.g. a reader that read ahead
+    /// before encryption was enabled. They have to be decrypted before anything else is read.
+    pub fn decrypt(&mut self, data: &mut [u8]) {
+        if let Some(cipher) = &mut self.cipher {
+            cipher.decrypt(data);
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CipherReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        if let Some(cipher) = &mut this.cipher {
+            // Whatever was in `buf` before has been decrypted already
+            cipher.decrypt(&mut buf.filled_mut()[filled..]);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Encrypts everything that is written to it into a buffer that is reused between writes,
+/// and writes that buffer to `W` when it is flushed or grows past [`WRITE_BUFFER_SIZE`].
+pub struct CipherWriter<W> {
+    inner: W,
+    cipher: Option<Cipher>,
+    /// Encrypted bytes that haven't been written to `inner` yet.
+    buf: BytesMut,
+}
+
+impl<W> CipherWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            cipher: None,
+            buf: BytesMut::new(),
+        }
+    }
+
+    /// Encrypts everything that is written from now on, buffered bytes stay as they are.
+    pub fn enable_encryption(&mut self, key: &[u8]) -> Result<()> {
+        self.cipher = Some(new_cipher(key)?);
+        Ok(())
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// The number of bytes waiting to be written to the stream.
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Lets `f` append plaintext to the buffer and encrypts what it appended,
+    /// so packets can be encoded straight into the buffer. Nothing is kept if `f` fails.
+    pub fn append<E>(
+        &mut self,
+        f: impl FnOnce(&mut BytesMut) -> std::result::Result<(), E>,
+    ) -> std::result::Result<(), E> {
+        let start = self.buf.len();
+        if let Err(e) = f(&mut self.buf) {
+            self.buf.truncate(start);
+            return Err(e);
+        }
+        if let Some(cipher) = &mut self.cipher {
+            cipher.encrypt(&mut self.buf[start..]);
+        }
+        Ok(())
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Returns the stream, anything that hasn't been flushed is lost.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: AsyncWrite + Unpin> CipherWriter<W> {
+    /// Writes the buffer to the stream without flushing the stream.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.buf.is_empty() {
+            let written = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.buf))?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.buf.advance(written);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for CipherWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.buf.len() >= WRITE_BUFFER_SIZE {
+            ready!(this.poll_drain(cx))?;
+        }
+        this.append(|b| {
+            b.extend_from_slice(buf);
+            Ok::<_, io::Error>(())
+        })?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,200 @@
+//! Logging into servers as a client.
+//! ```ignore
+//! let (mut conn, success) = client::login("mc.hypixel.net", &auth, &LoginOptions::default()).await?;
+//! println!("logged in as {}", success.username);
+//! ```
+//! Offline mode servers only need a name, which works without the `online` feature:
+//! ```ignore
+//! let (mut conn, success) = client::login("localhost", "Notch", &LoginOptions::default()).await?;
+//! ```
+#[cfg(feature = "online")]
+use super::session::{self, DEFAULT_SESSION_URL};
+use super::{
+    conn::Conn,
+    packet::{ProtocolState, Side},
+    status::split_addr,
+    Error, Result,
+};
+#[cfg(feature = "online")]
+use crate::auth::Auth;
+use crate::p47::{enums::HandshakeState, handshaking, login, PROTOCOL_VERSION};
+use rand::Rng;
+use rsa::{pkcs8::DecodePublicKey, Pkcs1v15Encrypt, RsaPublicKey};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+pub use super::status::DEFAULT_PORT;
+
+/// How long connecting and logging in may take by default.
+pub const DEFAULT_LOGIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct LoginOptions {
+    /// The base url of the session server, this is [`DEFAULT_SESSION_URL`] unless you want to test against your own.
+    #[cfg(feature = "online")]
+    pub session_url: String,
+    #[cfg(feature = "online")]
+    pub http: reqwest::Client,
+    /// How long connecting and logging in may take each before they fail with [`Error::Timeout`],
+    /// `None` waits forever.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for LoginOptions {
+    fn default() -> Self {
+        Self {
+            #[cfg(feature = "online")]
+            session_url: DEFAULT_SESSION_URL.to_owned(),
+            #[cfg(feature = "online")]
+            http: reqwest::Client::new(),
+            timeout: Some(DEFAULT_LOGIN_TIMEOUT),
+        }
+    }
+}
+
+/// Who logs in, `&str` and `&Auth` convert into it.
+#[derive(Debug, Clone, Copy)]
+pub enum Account<'a> {
+    /// Only a name, which works on offline mode servers.
+    Offline(&'a str),
+    /// Joins the server through the session server when it asks for encryption.
+    #[cfg(feature = "online")]
+    Online(&'a Auth),
+}
+
+impl Account<'_> {
+    pub fn name(&self) -> &str {
+        match self {
+            Account::Offline(name) => name,
+            #[cfg(feature = "online")]
+            Account::Online(auth) => &auth.name,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Account<'a> {
+    fn from(name: &'a str) -> Self {
+        Account::Offline(name)
+    }
+}
+
+#[cfg(feature = "online")]
+impl<'a> From<&'a Auth> for Account<'a> {
+    fn from(auth: &'a Auth) -> Self {
+        Account::Online(auth)
+    }
+}
+
+/// Connects to `addr` and logs in, the returned connection is in the play state.
+/// `addr` is a host with an optional port, like in the server list.
+///
+/// If the server kicks the player the error is [`Error::Disconnected`]:
+/// ```ignore
+/// match client::login(addr, &auth, &options).await {
+///     Ok((conn, success)) => { /* ... */ }
+///     Err(Error::Disconnected { reason }) => println!("kicked: {}", reason),
+///     Err(e) => return Err(e),
+/// }
+/// ```
+/// Servers in online mode need an [`Account::Online`] and the `online` feature.
+pub async fn login(
+    addr: &str,
+    account: impl Into<Account<'_>>,
+    options: &LoginOptions,
+) -> Result<(Conn, login::clientbound::LoginSuccess)> {
+    let (host, port) = split_addr(addr)?;
+    let conn = match options.timeout {
+        Some(timeout) => Conn::connect_timeout((host, port), timeout).await?,
+        None => Conn::connect((host, port)).await?,
+    };
+    login_conn(conn, host, port, account, options).await
+}
+
+/// Same as [`login`] but for an existing connection, `host` and `port` are only sent in the handshake.
+/// The login timeout replaces any deadline that was set on `conn`, and the errors are the same as for [`login`].
+pub async fn login_conn<R, W>(
+    mut conn: Conn<R, W>,
+    host: &str,
+    port: u16,
+    account: impl Into<Account<'_>>,
+    options: &LoginOptions,
+) -> Result<(Conn<R, W>, login::clientbound::LoginSuccess)>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let account = account.into();
+    conn.set_side(Side::Client);
+    conn.set_deadline(options.timeout.map(|timeout| Instant::now() + timeout));
+    conn.send_packet(handshaking::serverbound::Handshake {
+        protocol_version: PROTOCOL_VERSION,
+        server_address: host.to_owned(),
+        server_port: port,
+        next_state: HandshakeState::Login,
+    })
+    .await?;
+    conn.set_state(ProtocolState::Login);
+    conn.send_packet(login::serverbound::LoginStart {
+        name: account.name().to_owned(),
+    })
+    .await?;
+
+    loop {
+        match conn.read_typed().await? {
+            login::Clientbound::Disconnect(packet) => {
+                return Err(Error::Disconnected {
+                    reason: packet.reason,
+                });
+            }
+            login::Clientbound::EncryptionRequest(packet) => {
+                let shared_secret = rand::thread_rng().gen::<[u8; 16]>();
+                #[cfg(feature = "online")]
+                if let Account::Online(auth) = account {
+                    let hash =
+                        session::server_hash(&packet.server_id, &shared_secret, &packet.public_key);
+                    session::join(
+                        &options.http,
+                        &options.session_url,
+                        &auth.token,
+                        &auth.uuid,
+                        &hash,
+                    )
+                    .await?;
+                }
+                encrypt(&mut conn, packet, &shared_secret).await?;
+            }
+            login::Clientbound::SetCompression(packet) => {
+                conn.set_compression_threshhold(packet.threshold);
+            }
+            login::Clientbound::LoginSuccess(packet) => {
+                conn.set_state(ProtocolState::Play);
+                conn.set_deadline(None);
+                return Ok((conn, packet));
+            }
+        }
+    }
+}
+
+/// Answers the encryption request with `shared_secret` and encrypts `conn`.
+/// Online accounts have to join through the session server before.
+async fn encrypt<R, W>(
+    conn: &mut Conn<R, W>,
+    packet: login::clientbound::EncryptionRequest,
+    shared_secret: &[u8],
+) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let key = RsaPublicKey::from_public_key_der(&packet.public_key)?;
+    let response = {
+        let mut rng = rand::thread_rng();
+        login::serverbound::EncryptionResponse {
+            shared_secret: key.encrypt(&mut rng, Pkcs1v15Encrypt, shared_secret)?,
+            verify_token: key.encrypt(&mut rng, Pkcs1v15Encrypt, &packet.verify_token)?,
+        }
+    };
+    conn.send_packet(response).await?;
+    conn.enable_encryption(shared_secret)?;
+    Ok(())
+}
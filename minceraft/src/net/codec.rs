@@ -0,0 +1,332 @@
+//! Packet framing as a [`tokio_util::codec`] pair.
+//! ```ignore
+//! let mut frames = FramedRead::new(stream, PacketCodec::default());
+//! while let Some(packet) = frames.next().await {
+//!     println!("{:?}", packet?.id);
+//! }
+//! ```
+use super::{
+    compression::{Compressor, Flate2Compressor},
+    packet::RawPacket,
+    stats::Stats,
+    types::*,
+    Error, Limit, Result,
+};
+use bytes::{Buf, BufMut, BytesMut};
+use std::{fmt, io::Write};
+
+/// The largest uncompressed packet the protocol allows.
+pub const MAX_DATA_LENGTH: i32 = 2097152;
+
+/// The largest frame vanilla accepts, which is the largest length that fits in three bytes.
+pub const MAX_FRAME_LENGTH: usize = 2097151;
+
+/// The most elements an array field may have by default.
+pub const MAX_ARRAY_LENGTH: usize = 1024 * 1024;
+
+/// The largest number of bytes a VarInt can take up.
+const MAX_VAR_INT_LENGTH: usize = 5;
+
+/// The frame size from which connections compress and decompress on the blocking thread pool by default.
+pub const DEFAULT_OFFLOAD_THRESHOLD: usize = 64 * 1024;
+
+/// Upper bounds for the lengths an incoming packet declares, so the other side can't make us allocate
+/// or inflate more than this. The defaults are what vanilla accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The largest frame, which is the packet before decompression.
+    pub max_frame_length: usize,
+    /// The largest packet after decompression.
+    pub max_data_length: usize,
+    /// The most elements an array field may have.
+    pub max_array_length: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_frame_length: MAX_FRAME_LENGTH,
+            max_data_length: MAX_DATA_LENGTH as usize,
+            max_array_length: MAX_ARRAY_LENGTH,
+        }
+    }
+}
+
+/// Splits a byte stream into [`RawPacket`]s and back.
+/// A negative threshold means compression is disabled, which is the initial state of every connection.
+pub struct PacketCodec {
+    threshold: i32,
+    limits: Limits,
+    compressor: Box<dyn Compressor>,
+    // Boxed so codecs without stats stay small
+    stats: Option<Box<Stats>>,
+}
+
+impl Default for PacketCodec {
+    fn default() -> Self {
+        Self::new(-1)
+    }
+}
+
+impl PacketCodec {
+    pub fn new(threshold: i32) -> Self {
+        Self {
+            threshold,
+            limits: Limits::default(),
+            compressor: Box::new(Flate2Compressor::default()),
+            stats: None,
+        }
+    }
+
+    /// Compresses and decompresses with `compressor` instead of a [`Flate2Compressor`].
+    pub fn set_compressor(&mut self, compressor: impl Compressor + 'static) {
+        self.compressor = Box::new(compressor);
+    }
+
+    /// Uses a [`Flate2Compressor`] with `level`, from 0 (store only) to 9 (smallest).
+    pub fn set_compression_level(&mut self, level: u32) {
+        self.set_compressor(Flate2Compressor::new(level));
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Sets the limits incoming frames are checked against, outgoing frames aren't checked.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn threshold(&self) -> i32 {
+        self.threshold
+    }
+
+    pub fn set_compression_threshhold(&mut self, threshhold: i32) {
+        self.threshold = threshhold;
+    }
+
+    /// Starts counting the packets that are encoded and decoded, see [`Stats`].
+    /// Nothing is counted until then. Enabling them again keeps the counters.
+    pub fn enable_stats(&mut self) {
+        if self.stats.is_none() {
+            self.stats = Some(Box::default());
+        }
+    }
+
+    /// Stops counting and throws away the counters.
+    pub fn disable_stats(&mut self) {
+        self.stats = None;
+    }
+
+    /// The counters, `None` if they aren't enabled.
+    pub fn stats(&self) -> Option<&Stats> {
+        self.stats.as_deref()
+    }
+
+    /// Sets the counters back to zero if they are enabled.
+    pub fn reset_stats(&mut self) {
+        if let Some(stats) = &mut self.stats {
+            **stats = Stats::default();
+        }
+    }
+
+    /// Counts a packet that was framed somewhere else as sent, like a [`PreparedPacket`](super::packet::PreparedPacket).
+    pub(crate) fn record_sent(&mut self, id: VarInt, bytes: usize, uncompressed_bytes: usize) {
+        if let Some(stats) = &mut self.stats {
+            let compressed = self.threshold >= 0 && uncompressed_bytes >= self.threshold as usize;
+            stats.sent.record(id, bytes, uncompressed_bytes, compressed);
+        }
+    }
+
+    fn record_received(&mut self, id: VarInt, frame_len: usize, data_len: usize, compressed: bool) {
+        if let Some(stats) = &mut self.stats {
+            let bytes = var_int_len(frame_len) + frame_len;
+            stats.received.record(id, bytes, data_len, compressed);
+        }
+    }
+
+    /// Decodes a single frame, `frame` must not contain the length prefix.
+    pub fn decode_frame(&mut self, frame: &[u8]) -> Result<RawPacket> {
+        let mut buf = frame;
+
+        if self.threshold >= 0 {
+            let data_len = VarInt::read_from(&mut buf)?.0;
+
+            if data_len != 0 {
+                if data_len < self.threshold {
+                    return Err(Error::Invalid(format!(
+                        "data length is smaller than threshold: {} < {}",
+                        data_len, self.threshold
+                    )));
+                }
+
+                let data_len = data_len as usize;
+                if data_len > self.limits.max_data_length {
+                    return Err(Error::TooLarge {
+                        limit: Limit::Data,
+                        len: data_len,
+                        max: self.limits.max_data_length,
+                    });
+                }
+
+                // Inflate at most one byte more than declared, which is enough to tell that it lied
+                let mut decompressed = Vec::new();
+                self.compressor
+                    .decompress(buf, data_len + 1, &mut decompressed)
+                    .map_err(Error::Compression)?;
+
+                if decompressed.len() != data_len {
+                    return Err(Error::DataLengthMismatch {
+                        declared: data_len,
+                        actual: decompressed.len(),
+                    });
+                }
+
+                let packet = Self::split_id(&decompressed)?;
+                self.record_received(packet.id, frame.len(), data_len, true);
+                return Ok(packet);
+            }
+        }
+
+        let packet = Self::split_id(buf)?;
+        self.record_received(packet.id, frame.len(), buf.len(), false);
+        Ok(packet)
+    }
+
+    /// Takes the next complete frame out of `src` without its length prefix, but doesn't decode it yet.
+    /// Returns `None` if `src` doesn't hold a complete frame.
+    pub fn next_frame(&self, src: &mut BytesMut) -> Result<Option<BytesMut>> {
+        let (len, len_size) = match peek_var_int(src)? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        if len < 0 {
+            return Err(Error::Invalid(format!("packet length is negative: {}", len)));
+        }
+        if len as usize > self.limits.max_frame_length {
+            return Err(Error::TooLarge {
+                limit: Limit::Frame,
+                len: len as usize,
+                max: self.limits.max_frame_length,
+            });
+        }
+
+        let frame_len = len_size + len as usize;
+        if src.len() < frame_len {
+            // Make room for the rest of the frame so it can be read in one go
+            src.reserve(frame_len - src.len());
+            return Ok(None);
+        }
+
+        src.advance(len_size);
+        Ok(Some(src.split_to(len as usize)))
+    }
+
+    fn split_id(mut buf: &[u8]) -> Result<RawPacket> {
+        let id = VarInt::read_from(&mut buf)?;
+        Ok(RawPacket {
+            id,
+            data: buf.to_vec(),
+        })
+    }
+
+    /// Encodes `packet` including its length prefix and appends it to `dst`.
+    pub fn encode_frame(&mut self, packet: &RawPacket, dst: &mut BytesMut) -> Result<()> {
+        let mut body = Vec::with_capacity(MAX_VAR_INT_LENGTH + packet.data.len());
+        packet.id.write_to(&mut body)?;
+        body.extend_from_slice(&packet.data);
+
+        let start = dst.len();
+        let mut w = dst.writer();
+
+        let compressed = self.threshold >= 0 && body.len() >= self.threshold as usize;
+        if self.threshold < 0 {
+            VarInt(body.len() as i32).write_to(&mut w)?;
+            w.write_all(&body)?;
+        } else if !compressed {
+            // The data length of 0 takes up one byte
+            VarInt(body.len() as i32 + 1).write_to(&mut w)?;
+            VarInt(0).write_to(&mut w)?;
+            w.write_all(&body)?;
+        } else {
+            let mut frame = Vec::new();
+            VarInt(body.len() as i32).write_to(&mut frame)?;
+            self.compressor
+                .compress(&body, &mut frame)
+                .map_err(Error::Compression)?;
+            VarInt(frame.len() as i32).write_to(&mut w)?;
+            w.write_all(&frame)?;
+        }
+
+        if let Some(stats) = &mut self.stats {
+            let bytes = dst.len() - start;
+            stats.sent.record(packet.id, bytes, body.len(), compressed);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for PacketCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PacketCodec")
+            .field("threshold", &self.threshold)
+            .field("limits", &self.limits)
+            .field("stats", &self.stats)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The number of bytes `value` takes up as a VarInt.
+pub(crate) fn var_int_len(value: usize) -> usize {
+    let bits = usize::BITS - value.leading_zeros();
+    (bits.max(1) as usize).div_ceil(7)
+}
+
+/// Reads a VarInt from the start of `buf` without consuming anything.
+/// Returns the value and the number of bytes it took up, or `None` if `buf` ends before the VarInt does.
+pub fn peek_var_int(buf: &[u8]) -> Result<Option<(i32, usize)>> {
+    let mut result = 0;
+
+    for (i, &read) in buf.iter().take(MAX_VAR_INT_LENGTH).enumerate() {
+        result |= i32::from(read & 0b0111_1111).overflowing_shl(7 * i as u32).0;
+
+        if read & 0b1000_0000 == 0 {
+            return Ok(Some((result, i + 1)));
+        }
+    }
+
+    if buf.len() >= MAX_VAR_INT_LENGTH {
+        return Err(Error::VarIntTooLong);
+    }
+
+    Ok(None)
+}
+
+impl tokio_util::codec::Decoder for PacketCodec {
+    type Item = RawPacket;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<RawPacket>> {
+        match self.next_frame(src)? {
+            Some(frame) => self.decode_frame(&frame).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+impl tokio_util::codec::Encoder<RawPacket> for PacketCodec {
+    type Error = Error;
+
+    fn encode(&mut self, packet: RawPacket, dst: &mut BytesMut) -> Result<()> {
+        self.encode_frame(&packet, dst)
+    }
+}
+
+impl tokio_util::codec::Encoder<&RawPacket> for PacketCodec {
+    type Error = Error;
+
+    fn encode(&mut self, packet: &RawPacket, dst: &mut BytesMut) -> Result<()> {
+        self.encode_frame(packet, dst)
+    }
+}
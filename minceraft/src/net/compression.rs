@@ -0,0 +1,124 @@
+//! The zlib implementation behind compressed packets.
+//! ```ignore
+//! // Trade ratio for cpu time
+//! conn.set_compression_level(1);
+//! // Or bring your own zlib
+//! conn.set_compressor(MyZlib::new());
+//! ```
+use flate2::{Compress, Decompress, FlushCompress, FlushDecompress, Status};
+use std::io;
+
+/// The level vanilla and [`Flate2Compressor::default`] compress with.
+pub const DEFAULT_COMPRESSION_LEVEL: u32 = 6;
+
+/// Compresses and decompresses the zlib streams of packets.
+/// Every codec has its own compressor, so it can keep its state from one packet to the next.
+pub trait Compressor: Send {
+    /// Compresses all of `data` into a zlib stream and appends it to `out`.
+    fn compress(&mut self, data: &[u8], out: &mut Vec<u8>) -> io::Result<()>;
+
+    /// Decompresses the zlib stream `data` and appends it to `out`, but stops after `max` bytes.
+    /// A stream that ends early is an error, one that is longer is not since the caller checks the length.
+    fn decompress(&mut self, data: &[u8], max: usize, out: &mut Vec<u8>) -> io::Result<()>;
+}
+
+/// The default [`Compressor`], which uses flate2 and reuses its zlib streams instead of allocating new ones.
+#[derive(Debug)]
+pub struct Flate2Compressor {
+    level: flate2::Compression,
+    // Created on first use, so connections that never compress don't pay for them
+    compress: Option<Compress>,
+    decompress: Option<Decompress>,
+}
+
+impl Flate2Compressor {
+    /// Creates a compressor with a level from 0 (store only) to 9 (smallest).
+    pub fn new(level: u32) -> Self {
+        Self {
+            level: flate2::Compression::new(level.min(9)),
+            compress: None,
+            decompress: None,
+        }
+    }
+
+    pub fn level(&self) -> u32 {
+        self.level.level()
+    }
+}
+
+impl Default for Flate2Compressor {
+    fn default() -> Self {
+        Self::new(DEFAULT_COMPRESSION_LEVEL)
+    }
+}
+
+impl Clone for Flate2Compressor {
+    /// Only the level is cloned, the streams are created again when they are needed.
+    fn clone(&self) -> Self {
+        Self::new(self.level())
+    }
+}
+
+impl Compressor for Flate2Compressor {
+    fn compress(&mut self, data: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+        let level = self.level;
+        let compress = self
+            .compress
+            .get_or_insert_with(|| Compress::new(level, true));
+        compress.reset();
+
+        // Enough for incompressible data most of the time, the loop grows it otherwise
+        out.reserve(data.len() + data.len() / 1000 + 64);
+        let mut read = 0;
+        loop {
+            let before = compress.total_in();
+            let status = compress
+                .compress_vec(&data[read..], out, FlushCompress::Finish)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            read += (compress.total_in() - before) as usize;
+
+            match status {
+                Status::StreamEnd => return Ok(()),
+                Status::Ok | Status::BufError => out.reserve(1024),
+            }
+        }
+    }
+
+    fn decompress(&mut self, data: &[u8], max: usize, out: &mut Vec<u8>) -> io::Result<()> {
+        let decompress = self.decompress.get_or_insert_with(|| Decompress::new(true));
+        decompress.reset(true);
+
+        let start = out.len();
+        out.resize(start + max, 0);
+        let (mut read, mut written) = (0, 0);
+        let result = loop {
+            let (before_in, before_out) = (decompress.total_in(), decompress.total_out());
+            let status = decompress.decompress(
+                &data[read..],
+                &mut out[start + written..],
+                FlushDecompress::Finish,
+            );
+            let progress = (
+                (decompress.total_in() - before_in) as usize,
+                (decompress.total_out() - before_out) as usize,
+            );
+            read += progress.0;
+            written += progress.1;
+
+            match status {
+                Ok(Status::StreamEnd) => break Ok(()),
+                Ok(_) if written == max => break Ok(()),
+                Ok(_) if progress == (0, 0) => {
+                    break Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "zlib stream ended early",
+                    ))
+                }
+                Ok(_) => {}
+                Err(e) => break Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+            }
+        };
+        out.truncate(start + written);
+        result
+    }
+}
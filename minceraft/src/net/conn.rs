@@ -1,19 +1,43 @@
-use super::packet::*;
-use aes::Aes128;
-use anyhow::Result;
-use cfb8::{
-    cipher::{AsyncStreamCipher, NewCipher},
-    Cfb8,
+#[cfg(feature = "p47")]
+use super::keep_alive::{Handled, KeepAlive, KeepAliveOptions, Tick};
+use super::{
+    cipher::{CipherReader, CipherWriter},
+    codec::{peek_var_int, Limits, PacketCodec, DEFAULT_OFFLOAD_THRESHOLD},
+    compression::{Compressor, Flate2Compressor},
+    packet::*,
+    stats::Stats,
+    Error, Result,
 };
+use bytes::{Buf, BytesMut};
 use futures::ready;
 use std::net::SocketAddr;
-use std::{convert::TryFrom, pin::Pin, task::Poll};
-use tokio::{net::{TcpStream, ToSocketAddrs}, task::spawn_blocking};
+use std::sync::{
+    atomic::{AtomicI32, AtomicU8, Ordering},
+    Arc,
+};
+use std::{
+    convert::TryFrom,
+    future::{self, Future},
+    mem,
+    pin::Pin,
+    task::Poll,
+    time::{Duration, Instant},
+};
+use tokio::{net::{TcpStream, ToSocketAddrs}, task::{spawn_blocking, JoinHandle}};
 use tokio::{
-    io::{self, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, BufWriter},
+    io::{
+        self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream, ReadHalf,
+        WriteHalf,
+    },
     net::tcp::{OwnedReadHalf, OwnedWriteHalf},
 };
 
+/// The size of the in-memory buffer of each direction of [`Conn::pair`].
+const PAIR_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The initial capacity of the read buffer of a connection.
+const READ_BUFFER_SIZE: usize = 8 * 1024;
+
 // Commented out because I don't see a reason for anyone to use this instead of `tokio::net::TcpListener`.
 //pub struct Listener(pub TcpListener);
 //
@@ -27,206 +51,1019 @@ use tokio::{
 //}
 
 /// Conn wraps around TcpStream to simplify sending and receiving packets.
-pub struct Conn {
-    pub peer: SocketAddr,
-    cipher: Option<Cipher>,
-    writer: BufWriter<OwnedWriteHalf>,
-    reader: BufReader<OwnedReadHalf>,
+/// It works with any other byte stream as well, see [`Conn::new`] and [`Conn::from_halves`].
+pub struct Conn<R = OwnedReadHalf, W = OwnedWriteHalf> {
+    /// The address of the other side, this is `None` if the connection isn't a socket.
+    pub peer: Option<SocketAddr>,
+    reader: ConnReader<R>,
+    writer: ConnWriter<W>,
     /// `threshold` is initially set to -1 but after that you need to handle it manually
     pub threshold: i32,
+    state: ProtocolState,
+    side: Option<Side>,
+    #[cfg(feature = "p47")]
+    keep_alive: Option<KeepAlive>,
+}
+
+/// The reading half of a [`Conn`], created by [`Conn::into_split`].
+pub struct ConnReader<R = OwnedReadHalf> {
+    pub peer: Option<SocketAddr>,
+    reader: CipherReader<R>,
+    /// Decrypted bytes that have been read but not consumed yet.
+    buf: BytesMut,
+    shared: Arc<Shared>,
+    side: Option<Side>,
+    offload_threshold: usize,
+    codec: PacketCodec,
+    read_timeout: Option<Duration>,
+    /// When bytes last arrived or the read timeout was set, which is where the read timeout counts from.
+    /// This is on tokio's clock, so it follows a paused clock in tests like the keep alives do.
+    last_read: tokio::time::Instant,
+    /// A frame that is being decoded on the blocking thread pool, together with the codec.
+    offloaded: Option<JoinHandle<(PacketCodec, Result<RawPacket>)>>,
+    deadline: Option<Instant>,
+}
+
+/// The writing half of a [`Conn`], created by [`Conn::into_split`].
+pub struct ConnWriter<W = OwnedWriteHalf> {
+    pub peer: Option<SocketAddr>,
+    writer: CipherWriter<W>,
+    shared: Arc<Shared>,
+    side: Option<Side>,
+    offload_threshold: usize,
+    codec: PacketCodec,
+    flush_policy: FlushPolicy,
+    last_flush: Instant,
+    /// A frame that is being encoded on the blocking thread pool, together with the codec.
+    offloaded: Option<JoinHandle<(PacketCodec, Result<BytesMut>)>>,
+    deadline: Option<Instant>,
+}
+
+/// What both halves of a split connection need to agree on.
+struct Shared {
+    threshold: AtomicI32,
+    state: AtomicU8,
 }
 
-struct Cipher {
-    write: Cfb8<Aes128>,
-    read: Cfb8<Aes128>,
+impl Shared {
+    fn state(&self) -> ProtocolState {
+        match self.state.load(Ordering::Relaxed) {
+            0 => ProtocolState::Handshaking,
+            1 => ProtocolState::Status,
+            2 => ProtocolState::Login,
+            _ => ProtocolState::Play,
+        }
+    }
+
+    fn set_state(&self, state: ProtocolState) {
+        let state = match state {
+            ProtocolState::Handshaking => 0,
+            ProtocolState::Status => 1,
+            ProtocolState::Login => 2,
+            ProtocolState::Play => 3,
+        };
+        self.state.store(state, Ordering::Relaxed);
+    }
+}
+
+/// Decides when sent packets are flushed to the underlying stream.
+/// Packets added with [`Conn::queue_packet`], [`Conn::feed`] and [`Conn::feed_prepared`] are never flushed
+/// on their own, the policy is applied by [`Conn::send_packet`], [`Conn::send_raw_packet`] and [`Conn::send_prepared`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// Flush after every packet, this is the default.
+    #[default]
+    Immediate,
+    /// Only flush when [`Conn::flush`] is called.
+    Manual,
+    /// Flush once at least this many bytes are buffered.
+    Bytes(usize),
+    /// Flush when this much time has passed since the last flush, e.g. once per tick.
+    Interval(Duration),
 }
 
-impl AsyncWrite for Conn {
+impl<W: AsyncWrite + Unpin> AsyncWrite for ConnWriter<W> {
     fn poll_write(
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
         buf: &[u8],
     ) -> std::task::Poll<Result<usize, std::io::Error>> {
         let this = self.get_mut();
-        match this.cipher {
-            Some(ref mut cipher) => {
-                let mut data = vec![0; buf.len()];
-                data[..buf.len()].clone_from_slice(&buf[..]);
-                cipher.write.encrypt(&mut data);
-                Pin::new(&mut this.writer).poll_write(cx, &data)
-            }
-            None => Pin::new(&mut this.writer).poll_write(cx, buf),
-        }
+        ready!(this.poll_offloaded(cx)).map_err(into_io_error)?;
+        Pin::new(&mut this.writer).poll_write(cx, buf)
     }
 
     fn poll_flush(
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Result<(), std::io::Error>> {
-        Pin::new(&mut self.get_mut().writer).poll_flush(cx)
+        let this = self.get_mut();
+        ready!(this.poll_offloaded(cx)).map_err(into_io_error)?;
+        ready!(Pin::new(&mut this.writer).poll_flush(cx))?;
+        this.last_flush = Instant::now();
+        Poll::Ready(Ok(()))
     }
 
     fn poll_shutdown(
         self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Result<(), std::io::Error>> {
-        Pin::new(&mut self.get_mut().writer).poll_shutdown(cx)
+        let this = self.get_mut();
+        ready!(this.poll_offloaded(cx)).map_err(into_io_error)?;
+        Pin::new(&mut this.writer).poll_shutdown(cx)
     }
+}
 
-    //fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-    //    match self.cipher.as_mut() {
-    //        Some(ref mut cipher) => {
-    //            let mut data = vec![0; buf.len()];
-    //            data[..buf.len()].clone_from_slice(&buf[..]);
-    //            cipher.write.encrypt(&mut data);
-    //
-    //            self.writer.write(&data)
-    //        }
-    //        None => self.writer.write(buf),
-    //    }
-    //}
-    //fn flush(&mut self) -> io::Result<()> {
-    //    self.writer.flush()
-    //}
-}
-
-impl AsyncRead for Conn {
+impl<R: AsyncRead + Unpin> AsyncRead for ConnReader<R> {
     fn poll_read(
         self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
         buf: &mut io::ReadBuf<'_>,
     ) -> std::task::Poll<std::io::Result<()>> {
         let this = self.get_mut();
-        match &mut this.cipher {
-            Option::None => Pin::new(&mut this.reader).poll_read(cx, buf),
-            Option::Some(cipher) => {
-                //let initial_filled = buf.filled().len();
-                ready!(Pin::new(&mut this.reader).poll_read(cx, buf))?;
-                let data = buf.filled_mut();
-                cipher.read.decrypt(data);
-                Poll::Ready(Ok(()))
-            }
+
+        // Hand out what is left over from reading packets first
+        if !this.buf.is_empty() {
+            let len = this.buf.len().min(buf.remaining());
+            buf.put_slice(&this.buf[..len]);
+            this.buf.advance(len);
+            return Poll::Ready(Ok(()));
         }
+
+        Pin::new(&mut this.reader).poll_read(cx, buf)
     }
+}
 
-    //fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-    //    match self.cipher.as_mut() {
-    //        Option::None => self.reader.read(buf),
-    //        Option::Some(cipher) => {
-    //            let ret = self.reader.read(buf)?;
-    //            cipher.read.decrypt(&mut buf[..ret]);
-    //
-    //            Ok(ret)
-    //        }
-    //    }
-    //}
+impl<R: Unpin, W: AsyncWrite + Unpin> AsyncWrite for Conn<R, W> {
+    fn poll_write(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<Result<usize, std::io::Error>> {
+        Pin::new(&mut self.get_mut().writer).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), std::io::Error>> {
+        Pin::new(&mut self.get_mut().writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), std::io::Error>> {
+        Pin::new(&mut self.get_mut().writer).poll_shutdown(cx)
+    }
+}
+
+impl<R: AsyncRead + Unpin, W: Unpin> AsyncRead for Conn<R, W> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().reader).poll_read(cx, buf)
+    }
 }
 
 impl TryFrom<TcpStream> for Conn {
-    type Error = anyhow::Error;
+    type Error = Error;
     fn try_from(stream: TcpStream) -> Result<Self> {
         let peer = stream.peer_addr()?;
-        let (reader, writer) = split_stream(stream);
-        Ok(Self {
-            peer,
-            cipher: None,
-            writer,
-            reader,
-            threshold: -1,
-        })
+        let (reader, writer) = stream.into_split();
+        let mut conn = Self::from_halves(reader, writer);
+        conn.set_peer(peer);
+        Ok(conn)
     }
 }
 
 impl TryInto<TcpStream> for Conn {
-    type Error = anyhow::Error;
+    type Error = Error;
     fn try_into(self) -> Result<TcpStream> {
-        let read_half = self.reader.into_inner();
-        let write_half = self.writer.into_inner();
-        Ok(read_half.reunite(write_half)?)
+        let read_half = self.reader.reader.into_inner();
+        let write_half = self.writer.writer.into_inner();
+        read_half.reunite(write_half).map_err(|_| Error::Reunite)
     }
 }
 
 impl Conn {
     /// Connects to a server.
-    pub async fn connect<A: ToSocketAddrs>(addr: A) -> anyhow::Result<Conn> {
+    pub async fn connect<A: ToSocketAddrs>(addr: A) -> Result<Conn> {
         let stream = TcpStream::connect(addr).await?;
-        let peer = stream.peer_addr()?;
-        let (reader, writer) = split_stream(stream);
-        Ok(Self {
-            peer,
-            cipher: None,
-            writer,
-            reader,
+        let mut conn = Self::try_from(stream)?;
+        conn.set_side(Side::Client);
+        Ok(conn)
+    }
+
+    /// Connects to a server, but fails with [`Error::Timeout`] if resolving `addr` and connecting take longer than `timeout`.
+    pub async fn connect_timeout<A: ToSocketAddrs>(addr: A, timeout: Duration) -> Result<Conn> {
+        tokio::time::timeout(timeout, Self::connect(addr))
+            .await
+            .map_err(|_| Error::Timeout)?
+    }
+}
+
+impl Conn<ReadHalf<DuplexStream>, WriteHalf<DuplexStream>> {
+    /// Creates a client and a server connection that are connected to each other in memory.
+    /// This is useful for testing protocol flows without any sockets.
+    pub fn pair() -> (Self, Self) {
+        let (a, b) = tokio::io::duplex(PAIR_BUFFER_SIZE);
+        let (mut client, mut server) = (Conn::new(a), Conn::new(b));
+        client.set_side(Side::Client);
+        server.set_side(Side::Server);
+        (client, server)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite> Conn<ReadHalf<S>, WriteHalf<S>> {
+    /// Creates a connection from any byte stream, e.g. a unix socket or a tls stream.
+    pub fn new(stream: S) -> Self {
+        let (reader, writer) = tokio::io::split(stream);
+        Self::from_halves(reader, writer)
+    }
+}
+
+impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> Conn<R, W> {
+    /// Creates a connection from separate reading and writing streams.
+    /// It starts in the handshaking state and doesn't know which side it is, see [`Conn::set_side`].
+    pub fn from_halves(reader: R, writer: W) -> Self {
+        let shared = Arc::new(Shared {
+            threshold: AtomicI32::new(-1),
+            state: AtomicU8::new(0),
+        });
+        Self {
+            peer: None,
+            reader: ConnReader {
+                peer: None,
+                reader: CipherReader::new(reader),
+                buf: BytesMut::with_capacity(READ_BUFFER_SIZE),
+                shared: shared.clone(),
+                side: None,
+                offload_threshold: DEFAULT_OFFLOAD_THRESHOLD,
+                codec: PacketCodec::default(),
+                read_timeout: None,
+                last_read: tokio::time::Instant::now(),
+                offloaded: None,
+                deadline: None,
+            },
+            writer: ConnWriter {
+                peer: None,
+                writer: CipherWriter::new(writer),
+                shared,
+                side: None,
+                offload_threshold: DEFAULT_OFFLOAD_THRESHOLD,
+                codec: PacketCodec::default(),
+                flush_policy: FlushPolicy::default(),
+                last_flush: Instant::now(),
+                offloaded: None,
+                deadline: None,
+            },
             threshold: -1,
-        })
+            state: ProtocolState::Handshaking,
+            side: None,
+            #[cfg(feature = "p47")]
+            keep_alive: None,
+        }
     }
 
-    //pub fn connect_timeout(addr: &SocketAddr, timeout: Duration) -> anyhow::Result<Self> {
-    //    let stream = TcpStream::connect_timeout(addr, timeout)?;
-    //    let (reader, writer) = split_stream(stream);
-    //    Ok(Self {
-    //        peer: *addr,
-    //        cipher: None,
-    //        writer,
-    //        reader,
-    //        threshhold: -1,
-    //    })
-    //}
-
-    pub async fn shutdown(self) -> Result<()> {
-        let mut stream: TcpStream = self.try_into()?;
-        stream.shutdown().await?;
-        Ok(())
+    fn set_peer(&mut self, peer: SocketAddr) {
+        self.peer = Some(peer);
+        self.reader.peer = Some(peer);
+        self.writer.peer = Some(peer);
+    }
+
+    /// Flushes and shuts down the writing side of the connection.
+    pub async fn shutdown(mut self) -> Result<()> {
+        self.writer.shutdown().await
+    }
+
+    /// Splits the connection into a reading and a writing half which can be used in different tasks.
+    /// Both halves share the compression threshold and protocol state, so setting them on one half also sets them on the other.
+    /// Encryption has to be enabled before splitting or on both halves separately.
+    /// Keep alives aren't handled by the halves anymore.
+    pub fn into_split(self) -> (ConnReader<R>, ConnWriter<W>) {
+        self.reader.shared.threshold.store(self.threshold, Ordering::Relaxed);
+        self.reader.shared.set_state(self.state);
+        (self.reader, self.writer)
+    }
+
+    pub fn state(&self) -> ProtocolState {
+        self.state
+    }
+
+    /// Switches to another protocol state, e.g. after sending or receiving `Handshake` or `LoginSuccess`.
+    pub fn set_state(&mut self, state: ProtocolState) {
+        self.state = state;
+    }
+
+    /// Which side of the connection this is, `None` if it is unknown.
+    pub fn side(&self) -> Option<Side> {
+        self.side
+    }
+
+    /// Once the side is known only packets in the right direction can be sent and read.
+    pub fn set_side(&mut self, side: Side) {
+        self.side = Some(side);
+        self.reader.side = Some(side);
+        self.writer.side = Some(side);
     }
 
     /// Sends a packet.
     /// Note that this function takes ownership of `packet`.
-    /// Use send_raw_packet if you need to reuse the packet.
-    pub async fn send_packet<T: Packet + Send + Sync + 'static>(&mut self, packet: T) -> anyhow::Result<()> {        
-        let encoded = spawn_blocking(move || -> Result<RawPacket> {
-                packet.encode()
-            }).await??;
-
-        encoded.pack(self, self.threshold).await?;
-        self.flush().await?;
-        Ok(())
+    /// Use [`Conn::send_prepared`] if you need to send it to more than one connection.
+    /// Whether it is flushed right away depends on the [`FlushPolicy`].
+    /// This fails if the packet doesn't belong to the current state or direction.
+    pub async fn send_packet<T: StatePacket>(&mut self, packet: T) -> Result<()> {
+        check_packet::<T>(self.state, self.side.map(Side::sends), T::STATE, T::DIRECTION)?;
+        self.send_raw_packet(packet.encode()?).await
     }
 
-    /// Sends a raw packet. This is useful when sending the same packet to multiple clients.
-    pub async fn send_raw_packet(&mut self, packet: RawPacket) -> anyhow::Result<()> {
-        packet.pack(self, self.threshold).await?;
-        self.flush().await?;
-        Ok(())
+    /// Sends a raw packet.
+    /// Raw packets aren't checked against the protocol state.
+    pub async fn send_raw_packet(&mut self, packet: RawPacket) -> Result<()> {
+        self.writer.feed_with(packet, self.threshold).await?;
+        self.writer.auto_flush().await
     }
 
+    /// Sends a packet that was framed ahead of time, only encrypting it for this connection.
+    /// This is how the same packet is sent to many clients, and fails if the packet was
+    /// prepared for another compression threshold. Like raw packets it isn't checked against the protocol state.
+    pub async fn send_prepared(&mut self, packet: &PreparedPacket) -> Result<()> {
+        self.writer.feed_prepared_with(packet, self.threshold).await?;
+        self.writer.auto_flush().await
+    }
+
+    /// Buffers a packet without flushing it, call [`Conn::flush`] to send everything that was queued.
+    /// ```ignore
+    /// for chunk in chunks {
+    ///     conn.queue_packet(chunk).await?;
+    /// }
+    /// conn.flush().await?;
+    /// ```
+    pub async fn queue_packet<T: StatePacket>(&mut self, packet: T) -> Result<()> {
+        check_packet::<T>(self.state, self.side.map(Side::sends), T::STATE, T::DIRECTION)?;
+        self.feed(packet.encode()?).await
+    }
+
+    /// Buffers a raw packet without flushing it, see [`Conn::queue_packet`].
+    pub async fn feed(&mut self, packet: RawPacket) -> Result<()> {
+        self.writer.feed_with(packet, self.threshold).await
+    }
+
+    /// Buffers a prepared packet without flushing it, see [`Conn::send_prepared`].
+    pub async fn feed_prepared(&mut self, packet: &PreparedPacket) -> Result<()> {
+        self.writer.feed_prepared_with(packet, self.threshold).await
+    }
+
+    /// Writes all buffered packets to the underlying stream.
+    pub async fn flush(&mut self) -> Result<()> {
+        self.writer.flush().await
+    }
+
+    pub fn flush_policy(&self) -> FlushPolicy {
+        self.writer.flush_policy
+    }
+
+    pub fn set_flush_policy(&mut self, policy: FlushPolicy) {
+        self.writer.flush_policy = policy;
+    }
+
+    /// Reads a packet. Unless the [`FlushPolicy`] is `Manual`, anything that is still buffered is flushed
+    /// before waiting for the other side, since it might be waiting for those packets.
+    /// Keep alives are handled here if they are enabled, see [`Conn::enable_keep_alive`].
+    /// Reading is cancel safe, so it can be raced against other futures in `tokio::select!` without losing packets.
     pub async fn read_packet(&mut self) -> Result<RawPacket> {
-        RawPacket::unpack(self, self.threshold).await
+        #[cfg(feature = "p47")]
+        if let (Some(keep_alive), Some(side), ProtocolState::Play) =
+            (&mut self.keep_alive, self.side, self.state)
+        {
+            return read_packet_keeping_alive(
+                &mut self.reader,
+                &mut self.writer,
+                keep_alive,
+                side,
+                self.threshold,
+            )
+            .await;
+        }
+
+        if !self.reader.has_packet() {
+            self.writer.flush_before_read().await?;
+        }
+        self.reader.read_packet_with(self.threshold).await
+    }
+
+    /// Waits until the other side sent something and returns true if it starts with the `0xFE` of a
+    /// legacy server list ping, which clients before 1.7 send instead of a handshake. Nothing is consumed.
+    /// See [`legacy`](super::legacy) for how to answer it.
+    #[cfg(feature = "p47")]
+    pub async fn is_legacy_ping(&mut self) -> Result<bool> {
+        if self.reader.buf.is_empty() {
+            self.reader.fill_buf().await?;
+        }
+        Ok(self.reader.buf[0] == super::legacy::PING)
+    }
+
+    /// The bytes that were read but not consumed yet.
+    #[cfg(feature = "p47")]
+    pub(crate) fn buffered(&self) -> &[u8] {
+        &self.reader.buf
+    }
+
+    /// Handles the keep alives of the play state from now on, which only works once the side is known.
+    /// Clients answer the keep alives of the server, servers send one every `interval` and
+    /// disconnect the client with [`Error::KeepAliveTimeout`] if it isn't answered within `timeout`.
+    /// Keep alives are only handled while reading with [`Conn::read_packet`] or [`Conn::read_typed`],
+    /// which never return them, and not at all after [`Conn::into_split`].
+    #[cfg(feature = "p47")]
+    pub fn enable_keep_alive(&mut self, options: KeepAliveOptions) {
+        self.keep_alive = Some(KeepAlive::new(options));
+    }
+
+    /// The round trip time of the last keep alive the server sent, `None` until there was one
+    /// or if keep alives aren't enabled. Only servers can time keep alives, so on the client side
+    /// this is the ping the server measured for our player, see [`Conn::reported_ping`].
+    #[cfg(feature = "p47")]
+    pub fn latency(&self) -> Option<Duration> {
+        self.keep_alive.as_ref().and_then(KeepAlive::latency)
+    }
+
+    /// The ping the server reports for our player in `PlayerListItem` on the client side,
+    /// `None` until it did, if keep alives aren't enabled or [`KeepAliveOptions::uuid`] isn't set.
+    /// This is what the server measured and not a round trip on this connection.
+    #[cfg(feature = "p47")]
+    pub fn reported_ping(&self) -> Option<Duration> {
+        self.keep_alive.as_ref().and_then(KeepAlive::reported_ping)
+    }
+
+    /// Reads a packet and decodes it as one of the packets in `E`.
+    /// This fails if `E` doesn't belong to the current state or direction.
+    /// ```ignore
+    /// match conn.read_typed::<login::Clientbound>().await? {
+    ///     login::Clientbound::LoginSuccess(p) => println!("logged in as {}", p.username),
+    ///     _ => {}
+    /// }
+    /// ```
+    pub async fn read_typed<E: PacketEnum>(&mut self) -> Result<E> {
+        check_packet::<E>(self.state, self.side.map(Side::receives), E::STATE, E::DIRECTION)?;
+        let packet = self.read_packet().await?;
+        E::decode_with(packet, &self.reader.limits())
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.reader.limits()
+    }
+
+    /// Sets the limits incoming packets are checked against, see [`Limits`].
+    /// Packets decoded by hand only get the array limit through [`Packet::decode_with`].
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.reader.set_limits(limits);
+    }
+
+    /// Compresses and decompresses packets with `compressor`, each half gets its own clone of it.
+    pub fn set_compressor<C: Compressor + Clone + 'static>(&mut self, compressor: C) {
+        self.reader.set_compressor(compressor.clone());
+        self.writer.set_compressor(compressor);
+    }
+
+    /// Compresses packets with a [`Flate2Compressor`] with `level`, from 0 (store only) to 9 (smallest).
+    /// The default is [`DEFAULT_COMPRESSION_LEVEL`](super::compression::DEFAULT_COMPRESSION_LEVEL).
+    pub fn set_compression_level(&mut self, level: u32) {
+        self.set_compressor(Flate2Compressor::new(level));
     }
 
     pub fn set_compression_threshhold(&mut self, threshhold: i32) {
         self.threshold = threshhold;
     }
 
-    pub fn enable_encryption(&mut self, key: &[u8]) -> anyhow::Result<()> {
-        let cipher = Cfb8::<Aes128>::new_from_slices(key, key);
-        let write = match cipher {
-            Ok(c) => c,
-            Err(e) => return Err(anyhow::anyhow!("{}", e)),
+    /// Sets the frame size from which compression and decompression run on the blocking thread pool
+    /// instead of the current task. Smaller packets are always handled inline.
+    /// The default is [`DEFAULT_OFFLOAD_THRESHOLD`], `usize::MAX` never offloads.
+    pub fn set_offload_threshold(&mut self, bytes: usize) {
+        self.reader.offload_threshold = bytes;
+        self.writer.offload_threshold = bytes;
+    }
+
+    /// Fails reads with [`Error::Timeout`] once nothing arrived for `timeout`, counted from the last bytes
+    /// that were read or from when the timeout was set. This catches peers that went away without closing
+    /// the connection, even while keep alives are being sent. `None`, the default, waits forever.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.reader.set_read_timeout(timeout);
+    }
+
+    /// Fails every read and flush that hasn't finished by `deadline` with [`Error::Timeout`],
+    /// until the deadline is changed or removed with `None`. This bounds whole exchanges like a login.
+    /// ```ignore
+    /// conn.set_deadline(Some(Instant::now() + Duration::from_secs(5)));
+    /// conn.send_packet(status::serverbound::Request {}).await?;
+    /// let response = conn.read_typed::<status::Clientbound>().await?;
+    /// conn.set_deadline(None);
+    /// ```
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.reader.deadline = deadline;
+        self.writer.deadline = deadline;
+    }
+
+    pub fn enable_encryption(&mut self, key: &[u8]) -> Result<()> {
+        self.reader.enable_encryption(key)?;
+        self.writer.enable_encryption(key)
+    }
+
+    /// Starts counting the packets that are sent and read, see [`Stats`].
+    /// Raw bytes written or read with the `AsyncWrite` and `AsyncRead` impls aren't counted.
+    pub fn enable_stats(&mut self) {
+        self.reader.enable_stats();
+        self.writer.enable_stats();
+    }
+
+    /// Stops counting and throws away the counters.
+    pub fn disable_stats(&mut self) {
+        self.reader.disable_stats();
+        self.writer.disable_stats();
+    }
+
+    /// A snapshot of the counters, `None` if they aren't enabled.
+    pub fn stats(&self) -> Option<Stats> {
+        Some(Stats {
+            sent: self.writer.stats()?.sent.clone(),
+            received: self.reader.stats()?.received.clone(),
+        })
+    }
+
+    /// Sets the counters back to zero if they are enabled.
+    pub fn reset_stats(&mut self) {
+        self.reader.reset_stats();
+        self.writer.reset_stats();
+    }
+}
+
+impl<R: AsyncRead + Unpin> ConnReader<R> {
+    pub async fn read_packet(&mut self) -> Result<RawPacket> {
+        let threshold = self.threshold();
+        self.read_packet_with(threshold).await
+    }
+
+    /// Returns true if a whole packet is buffered or being decoded, so reading it won't wait for the stream.
+    fn has_packet(&self) -> bool {
+        self.offloaded.is_some()
+            || matches!(
+                peek_var_int(&self.buf),
+                Ok(Some((len, len_size))) if self.buf.len() >= len_size + len.max(0) as usize
+            )
+    }
+
+    /// Decodes packets out of the read buffer and only reads from the stream when there is no complete frame,
+    /// so packets can be split up or put together in any way by the transport.
+    async fn read_packet_with(&mut self, threshold: i32) -> Result<RawPacket> {
+        loop {
+            if let Some(packet) = self.next_packet(threshold).await? {
+                return Ok(packet);
+            }
+            self.fill_buf().await?;
+        }
+    }
+
+    /// Decodes the next packet if a whole frame is buffered.
+    async fn next_packet(&mut self, threshold: i32) -> Result<Option<RawPacket>> {
+        let offloaded = match self.offloaded.take() {
+            Some(offloaded) => offloaded,
+            None => {
+                self.codec.set_compression_threshhold(threshold);
+                match self.codec.next_frame(&mut self.buf)? {
+                    // Only compressed frames are worth moving to another thread
+                    Some(frame) if threshold >= 0 && frame.len() >= self.offload_threshold => {
+                        let mut codec = mem::take(&mut self.codec);
+                        spawn_blocking(move || {
+                            let packet = codec.decode_frame(&frame);
+                            (codec, packet)
+                        })
+                    }
+                    Some(frame) => return self.codec.decode_frame(&frame).map(Some),
+                    None => return Ok(None),
+                }
+            }
         };
-        let cipher = Cfb8::<Aes128>::new_from_slices(key, key);
-        let read = match cipher {
-            Ok(c) => c,
-            Err(e) => return Err(anyhow::anyhow!("{}", e)),
+        // The task is kept until it finishes, so a read that is cancelled meanwhile doesn't lose the packet
+        let result = self.offloaded.insert(offloaded).await;
+        self.offloaded = None;
+        let (codec, packet) = result.map_err(io::Error::from)?;
+        self.codec = codec;
+        packet.map(Some)
+    }
+
+    /// Reads more bytes from the stream into the read buffer.
+    /// This is cancel safe, so it can be raced against timers, which don't restart the read timeout.
+    async fn fill_buf(&mut self) -> Result<()> {
+        let idle = self
+            .read_timeout
+            .map(|timeout| (self.last_read + timeout).into_std());
+        let deadline = match (idle, self.deadline) {
+            (Some(idle), Some(deadline)) => Some(idle.min(deadline)),
+            (idle, deadline) => idle.or(deadline),
         };
-        self.cipher = Some(Cipher { write, read });
+        if until(deadline, self.reader.read_buf(&mut self.buf)).await? == 0 {
+            // Only a stream that ends between packets is closed cleanly
+            return Err(match self.buf.is_empty() {
+                true => Error::Eof,
+                false => io::Error::from(io::ErrorKind::UnexpectedEof).into(),
+            });
+        }
+        self.last_read = tokio::time::Instant::now();
+        Ok(())
+    }
+
+    /// Reads a packet and decodes it as one of the packets in `E`, see [`Conn::read_typed`].
+    pub async fn read_typed<E: PacketEnum>(&mut self) -> Result<E> {
+        check_packet::<E>(self.state(), self.side.map(Side::receives), E::STATE, E::DIRECTION)?;
+        let packet = self.read_packet().await?;
+        E::decode_with(packet, &self.limits())
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.codec.limits()
+    }
+
+    /// Sets the limits of this half, see [`Conn::set_limits`].
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.codec.set_limits(limits);
+    }
+
+    /// Decompresses packets with `compressor`, see [`Conn::set_compressor`].
+    pub fn set_compressor(&mut self, compressor: impl Compressor + 'static) {
+        self.codec.set_compressor(compressor);
+    }
+
+    pub fn threshold(&self) -> i32 {
+        self.shared.threshold.load(Ordering::Relaxed)
+    }
+
+    /// Sets the compression threshold of both halves.
+    pub fn set_compression_threshhold(&mut self, threshhold: i32) {
+        self.shared.threshold.store(threshhold, Ordering::Relaxed);
+    }
+
+    pub fn state(&self) -> ProtocolState {
+        self.shared.state()
+    }
+
+    /// Sets the protocol state of both halves.
+    pub fn set_state(&mut self, state: ProtocolState) {
+        self.shared.set_state(state);
+    }
 
+    /// Sets the offload threshold of this half, see [`Conn::set_offload_threshold`].
+    pub fn set_offload_threshold(&mut self, bytes: usize) {
+        self.offload_threshold = bytes;
+    }
+
+    /// See [`Conn::set_read_timeout`].
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout;
+        self.last_read = tokio::time::Instant::now();
+    }
+
+    /// Sets the deadline of this half, see [`Conn::set_deadline`].
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// Enables encryption for incoming data only.
+    pub fn enable_encryption(&mut self, key: &[u8]) -> Result<()> {
+        self.reader.enable_encryption(key)?;
+        // Whatever was read ahead came after the last plaintext packet, so it is encrypted already
+        self.reader.decrypt(&mut self.buf);
         Ok(())
     }
+
+    /// Starts counting the packets that are read, see [`Conn::enable_stats`].
+    pub fn enable_stats(&mut self) {
+        self.codec.enable_stats();
+    }
+
+    pub fn disable_stats(&mut self) {
+        self.codec.disable_stats();
+    }
+
+    /// The counters of this half, only [`Stats::received`] is counted here.
+    pub fn stats(&self) -> Option<&Stats> {
+        self.codec.stats()
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.codec.reset_stats();
+    }
+
+    /// Puts the two halves back together, this fails if they didn't come from the same [`Conn`].
+    pub fn reunite<W>(self, writer: ConnWriter<W>) -> Result<Conn<R, W>> {
+        if !Arc::ptr_eq(&self.shared, &writer.shared) {
+            return Err(Error::Reunite);
+        }
+        Ok(Conn {
+            peer: self.peer,
+            threshold: self.threshold(),
+            state: self.state(),
+            side: self.side,
+            #[cfg(feature = "p47")]
+            keep_alive: None,
+            reader: self,
+            writer,
+        })
+    }
 }
 
-fn split_stream(stream: TcpStream) -> (BufReader<OwnedReadHalf>, BufWriter<OwnedWriteHalf>) {
-    let (reader, writer) = stream.into_split();
-    let reader = BufReader::new(reader);
-    let writer = BufWriter::new(writer);
-    (reader, writer)
+impl<W: AsyncWrite + Unpin> ConnWriter<W> {
+    /// Sends a packet, see [`Conn::send_packet`].
+    pub async fn send_packet<T: StatePacket>(&mut self, packet: T) -> Result<()> {
+        check_packet::<T>(self.state(), self.side.map(Side::sends), T::STATE, T::DIRECTION)?;
+        self.send_raw_packet(packet.encode()?).await
+    }
+
+    /// Sends a raw packet, see [`Conn::send_raw_packet`].
+    pub async fn send_raw_packet(&mut self, packet: RawPacket) -> Result<()> {
+        self.feed(packet).await?;
+        self.auto_flush().await
+    }
+
+    /// Buffers a packet without flushing it, see [`Conn::queue_packet`].
+    pub async fn queue_packet<T: StatePacket>(&mut self, packet: T) -> Result<()> {
+        check_packet::<T>(self.state(), self.side.map(Side::sends), T::STATE, T::DIRECTION)?;
+        self.feed(packet.encode()?).await
+    }
+
+    /// Buffers a raw packet without flushing it, see [`Conn::queue_packet`].
+    pub async fn feed(&mut self, packet: RawPacket) -> Result<()> {
+        let threshold = self.threshold();
+        self.feed_with(packet, threshold).await
+    }
+
+    /// Sends a prepared packet, see [`Conn::send_prepared`].
+    pub async fn send_prepared(&mut self, packet: &PreparedPacket) -> Result<()> {
+        self.feed_prepared(packet).await?;
+        self.auto_flush().await
+    }
+
+    /// Buffers a prepared packet without flushing it, see [`Conn::send_prepared`].
+    pub async fn feed_prepared(&mut self, packet: &PreparedPacket) -> Result<()> {
+        let threshold = self.threshold();
+        self.feed_prepared_with(packet, threshold).await
+    }
+
+    /// Writes all buffered packets to the underlying stream.
+    pub async fn flush(&mut self) -> Result<()> {
+        let deadline = self.deadline;
+        until(deadline, AsyncWriteExt::flush(self)).await
+    }
+
+    pub fn flush_policy(&self) -> FlushPolicy {
+        self.flush_policy
+    }
+
+    pub fn set_flush_policy(&mut self, policy: FlushPolicy) {
+        self.flush_policy = policy;
+    }
+
+    pub fn state(&self) -> ProtocolState {
+        self.shared.state()
+    }
+
+    /// Sets the protocol state of both halves.
+    pub fn set_state(&mut self, state: ProtocolState) {
+        self.shared.set_state(state);
+    }
+
+    /// Encodes and encrypts a packet straight into the write buffer.
+    async fn feed_with(&mut self, packet: RawPacket, threshold: i32) -> Result<()> {
+        future::poll_fn(|cx| self.poll_offloaded(cx)).await?;
+        self.codec.set_compression_threshhold(threshold);
+
+        // Only packets that get compressed are worth moving to another thread
+        if threshold >= 0 && packet.data.len() >= self.offload_threshold {
+            let mut codec = mem::take(&mut self.codec);
+            self.offloaded = Some(spawn_blocking(move || {
+                let mut frame = BytesMut::new();
+                let result = codec.encode_frame(&packet, &mut frame).map(|_| frame);
+                (codec, result)
+            }));
+            future::poll_fn(|cx| self.poll_offloaded(cx)).await
+        } else {
+            let codec = &mut self.codec;
+            self.writer.append(|buf| codec.encode_frame(&packet, buf))
+        }
+    }
+
+    /// Waits for the frame that is being encoded on the blocking thread pool and appends it to the write buffer.
+    /// The task is kept until it finishes, so a send that is cancelled meanwhile doesn't lose the packet or the codec,
+    /// and the packet still goes out before anything that is written after it.
+    fn poll_offloaded(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<()>> {
+        let Some(offloaded) = &mut self.offloaded else {
+            return Poll::Ready(Ok(()));
+        };
+        let result = ready!(Pin::new(offloaded).poll(cx));
+        self.offloaded = None;
+        let (codec, frame) = result.map_err(io::Error::from)?;
+        self.codec = codec;
+        let frame = frame?;
+        Poll::Ready(self.writer.append(|buf| {
+            buf.extend_from_slice(&frame);
+            Ok::<_, Error>(())
+        }))
+    }
+
+    /// Encrypts a prepared frame into the write buffer.
+    async fn feed_prepared_with(&mut self, packet: &PreparedPacket, threshold: i32) -> Result<()> {
+        future::poll_fn(|cx| self.poll_offloaded(cx)).await?;
+        if packet.threshold() != threshold {
+            return Err(Error::ThresholdMismatch {
+                prepared: packet.threshold(),
+                conn: threshold,
+            });
+        }
+        self.writer.append(|buf| {
+            buf.extend_from_slice(packet.frame());
+            Ok::<_, Error>(())
+        })?;
+        self.codec
+            .record_sent(packet.id(), packet.frame().len(), packet.uncompressed_len());
+        Ok(())
+    }
+
+    /// Flushes before waiting for packets unless the flush policy is `Manual`, see [`Conn::read_packet`].
+    async fn flush_before_read(&mut self) -> Result<()> {
+        let pending = self.writer.buffered() > 0 || self.offloaded.is_some();
+        if self.flush_policy != FlushPolicy::Manual && pending {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Flushes if the flush policy says so.
+    async fn auto_flush(&mut self) -> Result<()> {
+        let flush = match self.flush_policy {
+            FlushPolicy::Immediate => true,
+            FlushPolicy::Manual => false,
+            FlushPolicy::Bytes(bytes) => self.writer.buffered() >= bytes,
+            FlushPolicy::Interval(interval) => self.last_flush.elapsed() >= interval,
+        };
+        if flush {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    pub fn threshold(&self) -> i32 {
+        self.shared.threshold.load(Ordering::Relaxed)
+    }
+
+    /// Sets the compression threshold of both halves.
+    pub fn set_compression_threshhold(&mut self, threshhold: i32) {
+        self.shared.threshold.store(threshhold, Ordering::Relaxed);
+    }
+
+    /// Sets the offload threshold of this half, see [`Conn::set_offload_threshold`].
+    pub fn set_offload_threshold(&mut self, bytes: usize) {
+        self.offload_threshold = bytes;
+    }
+
+    /// Sets the deadline of this half, see [`Conn::set_deadline`].
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// Compresses packets with `compressor`, see [`Conn::set_compressor`].
+    pub fn set_compressor(&mut self, compressor: impl Compressor + 'static) {
+        self.codec.set_compressor(compressor);
+    }
+
+    /// Enables encryption for outgoing data only.
+    pub fn enable_encryption(&mut self, key: &[u8]) -> Result<()> {
+        self.writer.enable_encryption(key)
+    }
+
+    /// Starts counting the packets that are sent, see [`Conn::enable_stats`].
+    pub fn enable_stats(&mut self) {
+        self.codec.enable_stats();
+    }
+
+    pub fn disable_stats(&mut self) {
+        self.codec.disable_stats();
+    }
+
+    /// The counters of this half, only [`Stats::sent`] is counted here.
+    pub fn stats(&self) -> Option<&Stats> {
+        self.codec.stats()
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.codec.reset_stats();
+    }
+
+    /// Shuts down the writing side of the connection.
+    pub async fn shutdown(&mut self) -> Result<()> {
+        let deadline = self.deadline;
+        until(deadline, AsyncWriteExt::shutdown(self)).await
+    }
+}
+
+/// Reads packets until one of them isn't a keep alive, and answers or sends keep alives meanwhile.
+#[cfg(feature = "p47")]
+async fn read_packet_keeping_alive<R, W>(
+    reader: &mut ConnReader<R>,
+    writer: &mut ConnWriter<W>,
+    keep_alive: &mut KeepAlive,
+    side: Side,
+    threshold: i32,
+) -> Result<RawPacket>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    loop {
+        match keep_alive.tick(side)? {
+            Some(Tick::Send(packet)) => {
+                writer.feed_with(packet, threshold).await?;
+                writer.flush().await?;
+            }
+            Some(Tick::Timeout) => {
+                // The client is most likely gone already, so it doesn't matter if this fails
+                let disconnect = crate::p47::play::clientbound::Disconnect {
+                    reason: super::server::chat_text("Timed out"),
+                };
+                if let Ok(packet) = disconnect.encode() {
+                    if writer.feed_with(packet, threshold).await.is_ok() {
+                        writer.flush().await.ok();
+                    }
+                }
+                return Err(keep_alive.timeout_error());
+            }
+            None => {}
+        }
+
+        if let Some(packet) = reader.next_packet(threshold).await? {
+            match keep_alive.handle(packet, side)? {
+                Handled::Pass(packet) => return Ok(packet),
+                Handled::Reply(packet) => {
+                    writer.feed_with(packet, threshold).await?;
+                    writer.flush().await?;
+                }
+                Handled::Consumed => {}
+            }
+            continue;
+        }
+
+        writer.flush_before_read().await?;
+        match keep_alive.deadline(side) {
+            Some(deadline) => tokio::select! {
+                result = reader.fill_buf() => result?,
+                _ = tokio::time::sleep_until(deadline) => {}
+            },
+            None => reader.fill_buf().await?,
+        }
+    }
+}
+
+/// Runs `f` and fails with [`Error::Timeout`] if it hasn't finished by `deadline`.
+async fn until<T>(deadline: Option<Instant>, f: impl Future<Output = io::Result<T>>) -> Result<T> {
+    match deadline {
+        Some(deadline) => Ok(tokio::time::timeout_at(deadline.into(), f)
+            .await
+            .map_err(|_| Error::Timeout)??),
+        None => Ok(f.await?),
+    }
+}
+
+/// Unwraps the io errors of the stream and wraps everything else for the `AsyncWrite` impls.
+fn into_io_error(e: Error) -> io::Error {
+    match e {
+        Error::Io(e) => e,
+        e => io::Error::other(e),
+    }
+}
+
+/// Fails if a packet of `T` from `state` and `direction` can't be used on a connection
+/// in `conn_state` that sends or receives `conn_direction`.
+pub(crate) fn check_packet<T>(
+    conn_state: ProtocolState,
+    conn_direction: Option<Direction>,
+    state: ProtocolState,
+    direction: Direction,
+) -> Result<()> {
+    if state != conn_state {
+        return Err(Error::WrongState {
+            packet: std::any::type_name::<T>(),
+            state,
+            conn_state,
+        });
+    }
+    if let Some(conn_direction) = conn_direction {
+        if direction != conn_direction {
+            return Err(Error::WrongDirection {
+                packet: std::any::type_name::<T>(),
+                direction,
+                conn_direction,
+            });
+        }
+    }
+    Ok(())
 }
@@ -0,0 +1,141 @@
+//! Handling packets with a handler per packet type instead of one big `match`, see [`Dispatcher`].
+//! ```ignore
+//! let mut dispatcher = Dispatcher::new();
+//! dispatcher
+//!     .on::<play::clientbound::KeepAlive>(|conn, p| {
+//!         async move { conn.send_packet(play::serverbound::KeepAlive { id: p.id }).await }.boxed()
+//!     })
+//!     .fallback(|_, p| async move {
+//!         println!("unhandled packet {:?}", p.id);
+//!         Ok(())
+//!     }.boxed());
+//!
+//! let token = dispatcher.cancellation_token();
+//! tokio::spawn(async move {
+//!     tokio::time::sleep(Duration::from_secs(60)).await;
+//!     token.cancel();
+//! });
+//! dispatcher.run(&mut conn).await?;
+//! ```
+//! Reading is cancel safe, so no packet is lost when the dispatcher is cancelled.
+use super::{
+    conn::Conn,
+    packet::{Direction, ProtocolState, RawPacket, Side, StatePacket},
+    Result,
+};
+use futures::future::{self, BoxFuture, FutureExt};
+use std::collections::HashMap;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::tcp::{OwnedReadHalf, OwnedWriteHalf},
+};
+use tokio_util::sync::CancellationToken;
+
+/// A handler that got its packet decoded already, or the fallback.
+type Handler<R, W> =
+    Box<dyn for<'a> FnMut(&'a mut Conn<R, W>, RawPacket) -> BoxFuture<'a, Result<()>> + Send>;
+
+/// Hands each packet that is read from a connection to the handler registered for its type.
+/// Handlers get the connection as well, so they can reply, and return a boxed future,
+/// which is what `FutureExt::boxed` makes out of an `async move` block.
+pub struct Dispatcher<R = OwnedReadHalf, W = OwnedWriteHalf> {
+    handlers: HashMap<(ProtocolState, Direction, i32), Handler<R, W>>,
+    fallback: Option<Handler<R, W>>,
+    cancel: CancellationToken,
+}
+
+impl<R, W> Default for Dispatcher<R, W>
+where
+    R: AsyncRead + Unpin + Send,
+    W: AsyncWrite + Unpin + Send,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R, W> Dispatcher<R, W>
+where
+    R: AsyncRead + Unpin + Send,
+    W: AsyncWrite + Unpin + Send,
+{
+    pub fn new() -> Self {
+        Self {
+            handlers: HashMap::new(),
+            fallback: None,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// Calls `handler` with every `T` that is read, replacing the handler that was registered for `T` before.
+    /// A packet that doesn't decode as `T` stops [`Dispatcher::run`] with the error.
+    pub fn on<T: StatePacket>(
+        &mut self,
+        mut handler: impl for<'a> FnMut(&'a mut Conn<R, W>, T) -> BoxFuture<'a, Result<()>>
+            + Send
+            + 'static,
+    ) -> &mut Self {
+        let handler: Handler<R, W> = Box::new(move |conn, packet| {
+            let limits = conn.limits();
+            match T::decode_with(packet, &limits) {
+                Ok(packet) => handler(conn, packet),
+                Err(e) => future::ready(Err(e)).boxed(),
+            }
+        });
+        self.handlers
+            .insert((T::STATE, T::DIRECTION, T::ID.0), handler);
+        self
+    }
+
+    /// Calls `handler` with the packets that have no handler of their own, they are dropped without one.
+    pub fn fallback(
+        &mut self,
+        handler: impl for<'a> FnMut(&'a mut Conn<R, W>, RawPacket) -> BoxFuture<'a, Result<()>>
+            + Send
+            + 'static,
+    ) -> &mut Self {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    /// The token that stops [`Dispatcher::run`], it can be cloned into handlers or other tasks.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    /// Reads packets from `conn` and hands them to their handlers until the token is cancelled,
+    /// which returns `Ok`, or reading or a handler fails.
+    /// A handler that is running when the token is cancelled is finished first, and a packet that is
+    /// being read stays in `conn`, so the connection can be used for something else afterwards.
+    pub async fn run(&mut self, conn: &mut Conn<R, W>) -> Result<()> {
+        loop {
+            let packet = tokio::select! {
+                biased;
+                _ = self.cancel.cancelled() => return Ok(()),
+                packet = conn.read_packet() => packet?,
+            };
+            self.dispatch(conn, packet).await?;
+        }
+    }
+
+    /// Hands a single packet to its handler or the fallback, e.g. one that was read before the dispatcher took over.
+    /// If the side of `conn` isn't known, handlers for packets of either direction are used.
+    pub async fn dispatch(&mut self, conn: &mut Conn<R, W>, packet: RawPacket) -> Result<()> {
+        let state = conn.state();
+        let received = conn.side().map(Side::receives);
+        let key = [Direction::Clientbound, Direction::Serverbound]
+            .into_iter()
+            .filter(|&direction| received.is_none_or(|received| received == direction))
+            .map(|direction| (state, direction, packet.id.0))
+            .find(|key| self.handlers.contains_key(key));
+
+        let handler = match key {
+            Some(key) => self.handlers.get_mut(&key),
+            None => self.fallback.as_mut(),
+        };
+        match handler {
+            Some(handler) => handler(conn, packet).await,
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use super::packet::{Direction, ProtocolState, UnknownPacketId};
+use std::{fmt, io, time::Duration};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Everything that can go wrong while encoding, decoding, sending or receiving packets.
+/// Use [`Error::root`] to look past the packet and field an error happened in.
+/// More variants may be added, and some only exist with certain features.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The other side closed the connection.
+    Eof,
+    /// Any other error of the underlying stream.
+    Io(io::Error),
+    /// A VarInt or VarLong that is longer than 5 or 10 bytes.
+    VarIntTooLong,
+    /// A length that is larger than one of the [`Limit`]s.
+    TooLarge { limit: Limit, len: usize, max: usize },
+    /// Compressed data that couldn't be inflated or deflated.
+    Compression(io::Error),
+    /// Compressed data that inflated to a different length than it declared.
+    /// Inflating stops one byte after the declared length, so `actual` is at most `declared + 1`.
+    DataLengthMismatch { declared: usize, actual: usize },
+    /// A value that no variant of an enum made with `def_enum!` matches.
+    UnknownDiscriminant {
+        enum_name: &'static str,
+        value: String,
+    },
+    /// A packet id that isn't part of the packet enum it was decoded as.
+    UnknownPacketId(UnknownPacketId),
+    /// Encryption couldn't be set up, usually because the key doesn't have 16 bytes
+    /// or the rsa key or data of the encryption handshake is invalid.
+    Cipher(String),
+    /// A player couldn't be authenticated, e.g. because the verify token doesn't match
+    /// or the session server doesn't know that they joined.
+    Auth(String),
+    /// The server sent `Disconnect` during login, `reason` is a chat component.
+    Disconnected { reason: String },
+    /// A request to the session server failed.
+    #[cfg(feature = "online")]
+    Http(reqwest::Error),
+    /// A packet that doesn't belong to the state the connection is in.
+    WrongState {
+        packet: &'static str,
+        state: ProtocolState,
+        conn_state: ProtocolState,
+    },
+    /// A packet that is sent or read in the wrong direction.
+    WrongDirection {
+        packet: &'static str,
+        direction: Direction,
+        conn_direction: Direction,
+    },
+    /// A [`PreparedPacket`](super::packet::PreparedPacket) that was framed for another compression threshold
+    /// than the connection uses.
+    ThresholdMismatch { prepared: i32, conn: i32 },
+    /// A connection, read or flush took longer than its timeout or deadline.
+    Timeout,
+    /// The client didn't answer a keep alive within this timeout and was disconnected.
+    KeepAliveTimeout(Duration),
+    /// The halves passed to `ConnReader::reunite` came from different connections.
+    Reunite,
+    /// Malformed nbt data.
+    Nbt(nbt::Error),
+    /// Data that doesn't follow the protocol, e.g. invalid utf-8, a negative length or a bool that isn't 0 or 1.
+    Invalid(String),
+    /// An error while reading `field` of `packet`, which is the name of a packet or an enum variant.
+    Field {
+        packet: &'static str,
+        field: &'static str,
+        source: Box<Error>,
+    },
+}
+
+/// What a [`Error::TooLarge`] length belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// A frame, see [`Limits::max_frame_length`](super::codec::Limits::max_frame_length).
+    Frame,
+    /// A decompressed packet, see [`Limits::max_data_length`](super::codec::Limits::max_data_length).
+    Data,
+    /// An array field, see [`Limits::max_array_length`](super::codec::Limits::max_array_length).
+    Array,
+    /// A chat message, which can't be longer than 262144 bytes.
+    Chat,
+    /// An identifier, which can't be longer than 32767 bytes.
+    Identifier,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Limit::Frame => "packet",
+            Limit::Data => "decompressed packet",
+            Limit::Array => "array",
+            Limit::Chat => "chat message",
+            Limit::Identifier => "identifier",
+        })
+    }
+}
+
+impl Error {
+    /// Returns the error without the packet and field context around it.
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Field { source, .. } => source.root(),
+            e => e,
+        }
+    }
+
+    /// Returns true if the other side closed the connection.
+    pub fn is_eof(&self) -> bool {
+        matches!(self.root(), Error::Eof)
+    }
+
+    /// Returns true if the connection timed out, including timeouts reported by the operating system.
+    pub fn is_timeout(&self) -> bool {
+        match self.root() {
+            Error::Timeout | Error::KeepAliveTimeout(_) => true,
+            Error::Io(e) => e.kind() == io::ErrorKind::TimedOut,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Eof => write!(f, "connection closed"),
+            Error::Io(e) => write!(f, "{}", e),
+            Error::VarIntTooLong => write!(f, "Varint is too large"),
+            Error::TooLarge { limit, len, max } => {
+                write!(f, "{} is too large: {} > {}", limit, len, max)
+            }
+            Error::Compression(e) => write!(f, "invalid compressed data: {}", e),
+            Error::DataLengthMismatch { declared, actual } => write!(
+                f,
+                "decompressed length doesn't match data length: {} != {}",
+                actual, declared
+            ),
+            Error::UnknownDiscriminant { enum_name, value } => write!(
+                f,
+                "no discriminant for enum `{}` matched value {}",
+                enum_name, value
+            ),
+            Error::UnknownPacketId(e) => write!(f, "{}", e),
+            Error::Cipher(e) => write!(f, "failed to set up encryption: {}", e),
+            Error::Auth(e) => write!(f, "authentication failed: {}", e),
+            Error::Disconnected { reason } => write!(f, "disconnected during login: {}", reason),
+            #[cfg(feature = "online")]
+            Error::Http(e) => write!(f, "session server request failed: {}", e),
+            Error::WrongState {
+                packet,
+                state,
+                conn_state,
+            } => write!(
+                f,
+                "`{}` belongs to the {:?} state but the connection is in the {:?} state",
+                packet, state, conn_state
+            ),
+            Error::WrongDirection {
+                packet,
+                direction,
+                conn_direction,
+            } => write!(
+                f,
+                "`{}` is {:?} but the connection expects {:?} packets here",
+                packet, direction, conn_direction
+            ),
+            Error::ThresholdMismatch { prepared, conn } => write!(
+                f,
+                "packet was prepared for compression threshold {} but the connection uses {}",
+                prepared, conn
+            ),
+            Error::Timeout => write!(f, "timed out"),
+            Error::KeepAliveTimeout(timeout) => {
+                write!(f, "no keep alive reply within {:?}", timeout)
+            }
+            Error::Reunite => write!(
+                f,
+                "tried to reunite halves that are not from the same connection"
+            ),
+            Error::Nbt(e) => write!(f, "invalid nbt: {}", e),
+            Error::Invalid(e) => write!(f, "{}", e),
+            Error::Field { packet, field, .. } => {
+                write!(f, "failed to read field `{}` of `{}`", field, packet)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) | Error::Compression(e) => Some(e),
+            Error::UnknownPacketId(e) => Some(e),
+            Error::Nbt(e) => Some(e),
+            #[cfg(feature = "online")]
+            Error::Http(e) => Some(e),
+            Error::Field { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<UnknownPacketId> for Error {
+    fn from(e: UnknownPacketId) -> Self {
+        Error::UnknownPacketId(e)
+    }
+}
+
+impl From<nbt::Error> for Error {
+    fn from(e: nbt::Error) -> Self {
+        Error::Nbt(e)
+    }
+}
+
+#[cfg(feature = "online")]
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+#[cfg(feature = "p47")]
+impl From<rsa::Error> for Error {
+    fn from(e: rsa::Error) -> Self {
+        Error::Cipher(e.to_string())
+    }
+}
+
+#[cfg(feature = "p47")]
+impl From<rsa::pkcs8::spki::Error> for Error {
+    fn from(e: rsa::pkcs8::spki::Error) -> Self {
+        Error::Cipher(e.to_string())
+    }
+}
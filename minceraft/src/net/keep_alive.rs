@@ -0,0 +1,190 @@
+//! Answering and sending the keep alives of the 1.8 play state, see [`Conn::enable_keep_alive`](super::conn::Conn::enable_keep_alive).
+//! ```ignore
+//! conn.enable_keep_alive(KeepAliveOptions::default());
+//! loop {
+//!     // Keep alives are handled by the connection and never show up here
+//!     let packet = conn.read_packet().await?;
+//!     println!("latency: {:?}", conn.latency());
+//! }
+//! ```
+use super::{
+    packet::{Packet, RawPacket, Side},
+    types::{Decoder, Uuid, VarInt},
+    Error, Result,
+};
+use crate::p47::play::{self, clientbound::PlayerListAction};
+use std::time::Duration;
+// Tokio's clock so keep alives follow a paused clock in tests
+use tokio::time::Instant;
+
+/// How often servers send a keep alive by default.
+pub const DEFAULT_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How long servers wait for a reply by default, which is also how long vanilla waits for any packet.
+pub const DEFAULT_KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct KeepAliveOptions {
+    /// How often the server sends a keep alive, this is unused on the client side.
+    pub interval: Duration,
+    /// How long the server waits for the reply to a keep alive before it disconnects the client,
+    /// this is unused on the client side.
+    pub timeout: Duration,
+    /// The uuid of the player on the client side, whose ping is taken out of `PlayerListItem`,
+    /// see [`Conn::reported_ping`](super::conn::Conn::reported_ping). Without it clients have no latency.
+    pub uuid: Option<Uuid>,
+}
+
+impl Default for KeepAliveOptions {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_KEEP_ALIVE_INTERVAL,
+            timeout: DEFAULT_KEEP_ALIVE_TIMEOUT,
+            uuid: None,
+        }
+    }
+}
+
+/// The keep alive bookkeeping of one connection.
+#[derive(Debug)]
+pub(crate) struct KeepAlive {
+    options: KeepAliveOptions,
+    /// The id and send time of the keep alive the server is waiting for.
+    pending: Option<(i32, Instant)>,
+    last_sent: Instant,
+    next_id: i32,
+    /// The round trip time of the last keep alive the server sent.
+    latency: Option<Duration>,
+    /// The ping the server reported for our player.
+    reported_ping: Option<Duration>,
+}
+
+/// What became of a packet that was read.
+pub(crate) enum Handled {
+    /// It is for the user.
+    Pass(RawPacket),
+    /// It was a keep alive that has to be answered with this packet.
+    Reply(RawPacket),
+    /// It was a keep alive that needs no answer.
+    Consumed,
+}
+
+/// What the connection has to do once the deadline has passed.
+pub(crate) enum Tick {
+    /// Send this keep alive and flush it.
+    Send(RawPacket),
+    /// The client didn't answer in time.
+    Timeout,
+}
+
+impl KeepAlive {
+    pub(crate) fn new(options: KeepAliveOptions) -> Self {
+        Self {
+            options,
+            pending: None,
+            last_sent: Instant::now(),
+            next_id: 0,
+            latency: None,
+            reported_ping: None,
+        }
+    }
+
+    /// The measured round trip on the server side, and the reported ping on the client side.
+    pub(crate) fn latency(&self) -> Option<Duration> {
+        self.latency.or(self.reported_ping)
+    }
+
+    pub(crate) fn reported_ping(&self) -> Option<Duration> {
+        self.reported_ping
+    }
+
+    /// Takes keep alives out of the packets that are read.
+    pub(crate) fn handle(&mut self, packet: RawPacket, side: Side) -> Result<Handled> {
+        match side {
+            Side::Client if packet.id == play::clientbound::KeepAlive::ID => {
+                let id = play::clientbound::KeepAlive::decode(packet)?.id;
+                let reply = play::serverbound::KeepAlive { id }.encode()?;
+                Ok(Handled::Reply(reply))
+            }
+            Side::Client if packet.id == play::clientbound::PlayerListItem::ID => {
+                self.update_reported_ping(&packet);
+                Ok(Handled::Pass(packet))
+            }
+            Side::Server if packet.id == play::serverbound::KeepAlive::ID => {
+                let id = play::serverbound::KeepAlive::decode(packet)?.id;
+                // Replies to keep alives that were already given up on are ignored, like vanilla does
+                if let Some((pending, sent)) = self.pending {
+                    if id == pending {
+                        self.latency = Some(sent.elapsed());
+                        self.pending = None;
+                    }
+                }
+                Ok(Handled::Consumed)
+            }
+            _ => Ok(Handled::Pass(packet)),
+        }
+    }
+
+    /// Takes the ping of our player from a `PlayerListItem`, packets that don't decode are left to the user.
+    /// Only the actions that carry a ping are decoded, in place and only once.
+    fn update_reported_ping(&mut self, packet: &RawPacket) {
+        let Some(uuid) = self.options.uuid else {
+            return;
+        };
+        let mut data = packet.data.as_slice();
+        // The action comes first, 0 adds players and 2 updates their latency
+        if !matches!(VarInt::read_from(&mut data), Ok(VarInt(0 | 2))) {
+            return;
+        }
+        let Ok(item) = play::clientbound::PlayerListItem::read_from(&mut packet.data.as_slice())
+        else {
+            return;
+        };
+        let ping = match item.action {
+            PlayerListAction::AddPlayer { players } => {
+                players.iter().find(|p| p.uuid == uuid).map(|p| p.ping)
+            }
+            PlayerListAction::UpdateLatency { players } => {
+                players.iter().find(|p| p.uuid == uuid).map(|p| p.ping)
+            }
+            _ => None,
+        };
+        let Some(ping) = ping else {
+            return;
+        };
+        self.reported_ping = Some(Duration::from_millis(ping.max(0) as u64));
+    }
+
+    /// When [`KeepAlive::tick`] has something to do next, `None` on the client side.
+    pub(crate) fn deadline(&self, side: Side) -> Option<Instant> {
+        match (side, self.pending) {
+            (Side::Client, _) => None,
+            (Side::Server, Some((_, sent))) => Some(sent + self.options.timeout),
+            (Side::Server, None) => Some(self.last_sent + self.options.interval),
+        }
+    }
+
+    /// Sends the next keep alive or gives up on the client once their time has come.
+    pub(crate) fn tick(&mut self, side: Side) -> Result<Option<Tick>> {
+        let now = Instant::now();
+        match self.deadline(side) {
+            Some(deadline) if now >= deadline => {}
+            _ => return Ok(None),
+        }
+        if self.pending.is_some() {
+            return Ok(Some(Tick::Timeout));
+        }
+
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.pending = Some((id, now));
+        self.last_sent = now;
+        let packet = play::clientbound::KeepAlive { id }.encode()?;
+        Ok(Some(Tick::Send(packet)))
+    }
+
+    /// The error returned after the client timed out.
+    pub(crate) fn timeout_error(&self) -> Error {
+        Error::KeepAliveTimeout(self.options.timeout)
+    }
+}
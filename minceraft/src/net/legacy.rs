@@ -0,0 +1,183 @@
+//! The server list ping of clients before 1.7, which isn't made of packets.
+//! Clients send `0xFE 0x01` and servers answer with a kick packet (`0xFF`) whose message holds the status.
+//! [`Server`](super::server::Server) answers these on its own, [`ping`] sends one.
+//! ```ignore
+//! let status = legacy::ping("127.0.0.1:25565").await?;
+//! println!("{} ({}/{})", status.motd, status.online, status.max);
+//! ```
+use super::{
+    conn::Conn,
+    status::{StatusResponse, DEFAULT_PING_TIMEOUT},
+    Error, Result,
+};
+use std::time::Instant;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::ToSocketAddrs,
+};
+
+/// The first byte of a legacy ping.
+pub const PING: u8 = 0xFE;
+
+/// The id of the kick packet servers answer with.
+pub const KICK: u8 = 0xFF;
+
+/// The status of a server as legacy clients see it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LegacyStatus {
+    /// The protocol version of the server, `-1` for servers before 1.4, which don't send it.
+    pub protocol: i32,
+    /// The version name, empty for servers before 1.4.
+    pub version: String,
+    pub motd: String,
+    pub online: i32,
+    pub max: i32,
+}
+
+impl LegacyStatus {
+    /// Takes the status out of the status JSON of the modern server list ping, see [`StatusResponse`].
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(Self::from(&StatusResponse::from_json(json)?))
+    }
+
+    /// The kick message that 1.4 and later expect: `§1`, protocol, version, motd, online and max separated by `\0`.
+    pub fn to_kick_message(&self) -> String {
+        format!(
+            "§1\0{}\0{}\0{}\0{}\0{}",
+            self.protocol, self.version, self.motd, self.online, self.max
+        )
+    }
+
+    /// The kick message of servers before 1.4: motd, online and max separated by `§`.
+    /// The motd can't contain `§` in this format, so formatting codes are dropped.
+    pub fn to_beta_kick_message(&self) -> String {
+        let mut motd = String::with_capacity(self.motd.len());
+        let mut chars = self.motd.chars();
+        while let Some(c) = chars.next() {
+            if c == '§' {
+                // The code after it goes too
+                chars.next();
+            } else {
+                motd.push(c);
+            }
+        }
+        format!("{}§{}§{}", motd, self.online, self.max)
+    }
+
+    /// Parses either kind of kick message.
+    pub fn from_kick_message(message: &str) -> Result<Self> {
+        let invalid = || Error::Invalid(format!("invalid legacy status: {:?}", message));
+        let int = |s: &str| s.parse::<i32>().map_err(|_| invalid());
+
+        if let Some(fields) = message.strip_prefix("§1\0") {
+            let fields: Vec<&str> = fields.split('\0').collect();
+            match fields[..] {
+                [protocol, version, motd, online, max] => Ok(Self {
+                    protocol: int(protocol)?,
+                    version: version.to_owned(),
+                    motd: motd.to_owned(),
+                    online: int(online)?,
+                    max: int(max)?,
+                }),
+                _ => Err(invalid()),
+            }
+        } else {
+            // The motd is everything before the player counts
+            let mut fields = message.rsplitn(3, '§');
+            let (max, online, motd) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(max), Some(online), Some(motd)) => (max, online, motd),
+                _ => return Err(invalid()),
+            };
+            Ok(Self {
+                protocol: -1,
+                version: String::new(),
+                motd: motd.to_owned(),
+                online: int(online)?,
+                max: int(max)?,
+            })
+        }
+    }
+}
+
+impl From<&StatusResponse> for LegacyStatus {
+    /// The description is flattened to plain text, since legacy clients don't understand chat components.
+    fn from(status: &StatusResponse) -> Self {
+        let (online, max) = status
+            .players
+            .as_ref()
+            .map_or((0, 0), |players| (players.online, players.max));
+        Self {
+            protocol: status.version.protocol,
+            version: status.version.name.clone(),
+            motd: status.description.to_plain_text(),
+            online,
+            max,
+        }
+    }
+}
+
+/// Connects to `addr` and asks for its status with a legacy ping.
+/// Gives up after [`DEFAULT_PING_TIMEOUT`] like [`status::ping`](super::status::ping).
+pub async fn ping<A: ToSocketAddrs>(addr: A) -> Result<LegacyStatus> {
+    let mut conn = Conn::connect_timeout(addr, DEFAULT_PING_TIMEOUT).await?;
+    conn.set_deadline(Some(Instant::now() + DEFAULT_PING_TIMEOUT));
+    ping_conn(&mut conn).await
+}
+
+/// Same as [`ping`] but over an existing connection, which is finished afterwards.
+/// Use [`Conn::set_deadline`] to bound how long it takes.
+pub async fn ping_conn<R, W>(conn: &mut Conn<R, W>) -> Result<LegacyStatus>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    conn.write_all(&[PING, 0x01]).await?;
+    conn.flush().await?;
+
+    let id = match conn.read_u8().await {
+        Ok(id) => id,
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Err(Error::Eof),
+        Err(e) => return Err(e.into()),
+    };
+    if id != KICK {
+        return Err(Error::Invalid(format!(
+            "expected a kick packet but got {:#04x}",
+            id
+        )));
+    }
+    let len = conn.read_u16().await?;
+    let mut units = Vec::with_capacity(len as usize);
+    for _ in 0..len {
+        units.push(conn.read_u16().await?);
+    }
+    let message = String::from_utf16(&units)
+        .map_err(|_| Error::Invalid("legacy status isn't valid utf-16".to_owned()))?;
+    LegacyStatus::from_kick_message(&message)
+}
+
+/// Answers the legacy ping that was detected with [`Conn::is_legacy_ping`] and shuts the connection down.
+/// Clients before 1.4 only send `0xFE` and get the beta format, which is what vanilla does as well.
+pub(crate) async fn answer<R, W>(conn: &mut Conn<R, W>, status: &LegacyStatus) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let message = match conn.buffered() {
+        [PING] => status.to_beta_kick_message(),
+        _ => status.to_kick_message(),
+    };
+    let units: Vec<u16> = message.encode_utf16().collect();
+    let len = u16::try_from(units.len())
+        .map_err(|_| Error::Invalid("legacy status is too long".to_owned()))?;
+
+    let mut packet = Vec::with_capacity(3 + units.len() * 2);
+    packet.push(KICK);
+    packet.extend_from_slice(&len.to_be_bytes());
+    for unit in units {
+        packet.extend_from_slice(&unit.to_be_bytes());
+    }
+    conn.write_all(&packet).await?;
+    conn.flush().await?;
+    AsyncWriteExt::shutdown(conn).await?;
+    Ok(())
+}
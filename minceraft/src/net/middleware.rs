@@ -0,0 +1,268 @@
+//! Stacking reusable behaviour like logging or filtering on a connection, see [`Pipeline`].
+//! ```ignore
+//! struct Log;
+//!
+//! impl Middleware for Log {
+//!     fn inbound(&mut self, packet: RawPacket, cx: &mut Context) -> Result<Option<RawPacket>> {
+//!         println!("{:?} {:?}", cx.state(), packet.id);
+//!         Ok(Some(packet))
+//!     }
+//! }
+//!
+//! let mut pipeline = Pipeline::new(conn);
+//! pipeline.push(Log);
+//! pipeline.push(typed(|mut chat: play::serverbound::ChatMessage, _: &mut Context| {
+//!     chat.message = chat.message.replace("heck", "****");
+//!     Some(chat)
+//! }));
+//! let packet = pipeline.read_packet().await?;
+//! ```
+use super::{
+    codec::Limits,
+    conn::{check_packet, Conn},
+    packet::{Direction, PacketEnum, ProtocolState, RawPacket, Side, StatePacket},
+    Result,
+};
+use std::{collections::VecDeque, marker::PhantomData};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::tcp::{OwnedReadHalf, OwnedWriteHalf},
+};
+
+/// Sees the packets of a [`Pipeline`] before they are read or sent.
+/// Both methods return the packet to pass on, which may have been changed, or `None` to drop it.
+pub trait Middleware: Send {
+    /// Called with each packet that is read, before the middleware added after this one.
+    fn inbound(&mut self, packet: RawPacket, cx: &mut Context) -> Result<Option<RawPacket>> {
+        let _ = cx;
+        Ok(Some(packet))
+    }
+
+    /// Called with each packet that is sent, before the middleware added before this one.
+    fn outbound(&mut self, packet: RawPacket, cx: &mut Context) -> Result<Option<RawPacket>> {
+        let _ = cx;
+        Ok(Some(packet))
+    }
+}
+
+/// What a [`Middleware`] knows about the packet it is called with, and where it puts the packets it makes up.
+#[derive(Debug)]
+pub struct Context {
+    state: ProtocolState,
+    direction: Option<Direction>,
+    limits: Limits,
+    emitted: Vec<(Flow, RawPacket)>,
+}
+
+/// Which way a packet goes through the pipeline.
+#[derive(Debug, Clone, Copy)]
+enum Flow {
+    Inbound,
+    Outbound,
+}
+
+impl Context {
+    /// The protocol state of the connection.
+    pub fn state(&self) -> ProtocolState {
+        self.state
+    }
+
+    /// The direction of the packet, `None` if the side of the connection isn't known.
+    pub fn direction(&self) -> Option<Direction> {
+        self.direction
+    }
+
+    /// Sends `packet` after the current one.
+    /// It only goes through the middleware between this one and the connection.
+    pub fn send(&mut self, packet: RawPacket) {
+        self.emitted.push((Flow::Outbound, packet));
+    }
+
+    /// Hands `packet` to the reader after the current one, as if it had been read.
+    /// It only goes through the middleware between this one and the reader.
+    pub fn receive(&mut self, packet: RawPacket) {
+        self.emitted.push((Flow::Inbound, packet));
+    }
+}
+
+/// A middleware for a single packet type, see [`typed`].
+pub struct Typed<T, F> {
+    f: F,
+    packet: PhantomData<fn() -> T>,
+}
+
+/// Calls `f` with the decoded packet whenever a `T` is read or sent, other packets are passed on untouched.
+/// So are packets with the id of `T` that don't decode as one, a proxy forwards them as they are.
+/// `f` returns the packet to pass on or `None` to drop it.
+/// If the side of the connection isn't known, packets with the id of `T` are decoded in both directions.
+pub fn typed<T, F>(f: F) -> Typed<T, F>
+where
+    T: StatePacket,
+    F: FnMut(T, &mut Context) -> Option<T> + Send,
+{
+    Typed {
+        f,
+        packet: PhantomData,
+    }
+}
+
+impl<T, F> Typed<T, F>
+where
+    T: StatePacket,
+    F: FnMut(T, &mut Context) -> Option<T> + Send,
+{
+    fn handle(&mut self, packet: RawPacket, cx: &mut Context) -> Result<Option<RawPacket>> {
+        if packet.id != T::ID
+            || cx.state != T::STATE
+            || cx
+                .direction
+                .is_some_and(|direction| direction != T::DIRECTION)
+        {
+            return Ok(Some(packet));
+        }
+        let Ok(decoded) = T::read_with(&mut packet.data.as_slice(), &cx.limits) else {
+            return Ok(Some(packet));
+        };
+        (self.f)(decoded, cx)
+            .map(|packet| packet.encode())
+            .transpose()
+    }
+}
+
+impl<T, F> Middleware for Typed<T, F>
+where
+    T: StatePacket,
+    F: FnMut(T, &mut Context) -> Option<T> + Send,
+{
+    fn inbound(&mut self, packet: RawPacket, cx: &mut Context) -> Result<Option<RawPacket>> {
+        self.handle(packet, cx)
+    }
+
+    fn outbound(&mut self, packet: RawPacket, cx: &mut Context) -> Result<Option<RawPacket>> {
+        self.handle(packet, cx)
+    }
+}
+
+/// Wraps a [`Conn`] and runs every packet that is read or sent through a chain of [`Middleware`].
+/// Read packets go through the middleware in the order it was added and sent packets in reverse,
+/// so the first middleware is the one closest to the connection.
+/// Packets sent or read through [`Pipeline::conn_mut`] skip the middleware.
+pub struct Pipeline<R = OwnedReadHalf, W = OwnedWriteHalf> {
+    conn: Conn<R, W>,
+    middleware: Vec<Box<dyn Middleware>>,
+    /// Packets that made it through the middleware but weren't read yet.
+    received: VecDeque<RawPacket>,
+}
+
+impl<R, W> Pipeline<R, W>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    pub fn new(conn: Conn<R, W>) -> Self {
+        Self {
+            conn,
+            middleware: Vec::new(),
+            received: VecDeque::new(),
+        }
+    }
+
+    /// Adds `middleware` to the end of the chain, which is the side of the reader.
+    pub fn push(&mut self, middleware: impl Middleware + 'static) {
+        self.middleware.push(Box::new(middleware));
+    }
+
+    /// Sends a packet through the middleware, see [`Conn::send_packet`].
+    pub async fn send_packet<T: StatePacket>(&mut self, packet: T) -> Result<()> {
+        let direction = self.conn.side().map(Side::sends);
+        check_packet::<T>(self.conn.state(), direction, T::STATE, T::DIRECTION)?;
+        self.send_raw_packet(packet.encode()?).await
+    }
+
+    /// Sends a raw packet through the middleware, see [`Conn::send_raw_packet`].
+    pub async fn send_raw_packet(&mut self, packet: RawPacket) -> Result<()> {
+        let len = self.middleware.len();
+        self.run(Flow::Outbound, len, packet).await
+    }
+
+    /// Reads the next packet that makes it through the middleware, see [`Conn::read_packet`].
+    pub async fn read_packet(&mut self) -> Result<RawPacket> {
+        loop {
+            if let Some(packet) = self.received.pop_front() {
+                return Ok(packet);
+            }
+            let packet = self.conn.read_packet().await?;
+            self.run(Flow::Inbound, 0, packet).await?;
+        }
+    }
+
+    /// Reads a packet through the middleware and decodes it as one of the packets in `E`, see [`Conn::read_typed`].
+    pub async fn read_typed<E: PacketEnum>(&mut self) -> Result<E> {
+        let direction = self.conn.side().map(Side::receives);
+        check_packet::<E>(self.conn.state(), direction, E::STATE, E::DIRECTION)?;
+        let packet = self.read_packet().await?;
+        E::decode_with(packet, &self.conn.limits())
+    }
+
+    /// The wrapped connection, e.g. to change its state or compression threshold.
+    pub fn conn(&self) -> &Conn<R, W> {
+        &self.conn
+    }
+
+    pub fn conn_mut(&mut self) -> &mut Conn<R, W> {
+        &mut self.conn
+    }
+
+    /// Returns the connection, packets that went through the middleware but weren't read yet are lost.
+    pub fn into_inner(self) -> Conn<R, W> {
+        self.conn
+    }
+
+    /// Runs `packet` through the middleware starting at `next`, along with everything the middleware makes up on the way.
+    /// Inbound packets go through `next..` and outbound packets through `..next` in reverse.
+    async fn run(&mut self, flow: Flow, next: usize, packet: RawPacket) -> Result<()> {
+        let mut queue = VecDeque::from([(flow, next, packet)]);
+        while let Some((flow, next, packet)) = queue.pop_front() {
+            let order: Vec<usize> = match flow {
+                Flow::Inbound => (next..self.middleware.len()).collect(),
+                Flow::Outbound => (0..next).rev().collect(),
+            };
+            let mut packet = Some(packet);
+            for i in order {
+                let Some(current) = packet.take() else { break };
+                let mut cx = self.context(flow);
+                packet = match flow {
+                    Flow::Inbound => self.middleware[i].inbound(current, &mut cx)?,
+                    Flow::Outbound => self.middleware[i].outbound(current, &mut cx)?,
+                };
+                for (emitted, packet) in cx.emitted {
+                    // Made up packets continue from the middleware that made them up
+                    let next = match emitted {
+                        Flow::Inbound => i + 1,
+                        Flow::Outbound => i,
+                    };
+                    queue.push_back((emitted, next, packet));
+                }
+            }
+            match (flow, packet) {
+                (Flow::Inbound, Some(packet)) => self.received.push_back(packet),
+                (Flow::Outbound, Some(packet)) => self.conn.send_raw_packet(packet).await?,
+                (_, None) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn context(&self, flow: Flow) -> Context {
+        let direction = self.conn.side().map(|side| match flow {
+            Flow::Inbound => side.receives(),
+            Flow::Outbound => side.sends(),
+        });
+        Context {
+            state: self.conn.state(),
+            direction,
+            limits: self.conn.limits(),
+            emitted: Vec::new(),
+        }
+    }
+}
@@ -1,3 +1,27 @@
+#[cfg(feature = "p47")]
+pub mod client;
+pub mod cipher;
+pub mod codec;
+pub mod compression;
 pub mod conn;
+pub mod dispatch;
+mod error;
+#[cfg(feature = "p47")]
+pub mod keep_alive;
+#[cfg(feature = "p47")]
+pub mod legacy;
+pub mod middleware;
 pub mod packet;
+#[cfg(feature = "p47")]
+pub mod proxy;
+pub mod record;
+#[cfg(feature = "p47")]
+pub mod server;
+#[cfg(feature = "online")]
+pub mod session;
+pub mod stats;
+#[cfg(feature = "p47")]
+pub mod status;
 pub mod types;
+
+pub use error::{Error, Limit, Result};
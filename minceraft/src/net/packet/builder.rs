@@ -12,9 +12,15 @@ macro_rules! user_type {
     (ShortPrefixedArray <$inner:ident>) => {
         Vec<$inner>
     };
+    (IntPrefixedArray <$inner:ident>) => {
+        Vec<$inner>
+    };
     (LengthInferredByteArray) => {
         Vec<u8>
     };
+    (OptionalNbt) => {
+        Option<Nbt>
+    };
     (Angle) => {
         f32
     };
@@ -34,9 +40,18 @@ macro_rules! user_type_convert_to_writeable {
     (ShortPrefixedArray <$inner:ident>, $e:expr) => {
         ShortPrefixedArray::from($e.as_slice())
     };
+    (IntPrefixedArray <$inner:ident>, $e:expr) => {
+        IntPrefixedArray::from($e.as_slice())
+    };
     (LengthInferredByteArray, $e:expr) => {
         LengthInferredByteArray::from($e.as_slice())
     };
+    (OptionalNbt, $e:expr) => {
+        OptionalNbt::from($e.as_ref())
+    };
+    (Angle, $e:expr) => {
+        Angle::from(*$e)
+    };
     ($typ:ty, $e:expr) => {
         $e
     };
@@ -75,14 +90,28 @@ macro_rules! packets {
 
             #[allow(unused_imports, unused_variables)]
             impl Decoder for $packet {
-                fn read_from(buffer: &mut impl std::io::Read) -> anyhow::Result<Self>
+                fn read_from(buffer: &mut impl std::io::Read) -> $crate::net::Result<Self>
+                where
+                    Self: Sized
+                {
+                    Self::read_with(buffer, &$crate::net::codec::Limits::default())
+                }
+
+                #[allow(unused_imports, unused_variables)]
+                fn read_with(
+                    buffer: &mut impl std::io::Read,
+                    limits: &$crate::net::codec::Limits,
+                ) -> $crate::net::Result<Self>
                 where
                     Self: Sized
                 {
-                    use anyhow::Context as _;
                     $(
-                        let $field = <$typ $(<$generics>)?>::read_from(buffer)
-                            .context(concat!("failed to read field `", stringify!($field), "` of packet `", stringify!($packet), "`"))?
+                        let $field = <$typ $(<$generics>)?>::read_with(buffer, limits)
+                            .map_err(|e| $crate::net::Error::Field {
+                                packet: stringify!($packet),
+                                field: stringify!($field),
+                                source: Box::new(e),
+                            })?
                             .into();
                     )*
 
@@ -96,7 +125,7 @@ macro_rules! packets {
 
             #[allow(unused_variables)]
             impl Encoder for $packet {
-                fn write_to(&self, w: &mut impl std::io::Write) -> anyhow::Result<()> {
+                fn write_to(&self, w: &mut impl std::io::Write) -> $crate::net::Result<()> {
                     $(
                         user_type_convert_to_writeable!($typ $(<$generics>)?, &self.$field).write_to(w)?;
                     )*
@@ -155,24 +184,40 @@ macro_rules! def_enum {
         }
 
         impl $crate::net::types::Decoder for $ident {
-            fn read_from(buffer: &mut impl std::io::Read) -> anyhow::Result<Self>
+            fn read_from(buffer: &mut impl std::io::Read) -> $crate::net::Result<Self>
+                where
+                    Self: Sized
+            {
+                Self::read_with(buffer, &$crate::net::codec::Limits::default())
+            }
+
+            #[allow(unused_variables)]
+            fn read_with(
+                buffer: &mut impl std::io::Read,
+                limits: &$crate::net::codec::Limits,
+            ) -> $crate::net::Result<Self>
                 where
                     Self: Sized
             {
-                #[allow(unused_imports)]
-                use anyhow::Context as _;
                 let discriminant = <$discriminant_type>::read_from(buffer)
-                    .context(concat!("failed to read discriminant for enum type ", stringify!($ident)))?;
+                    .map_err(|e| $crate::net::Error::Field {
+                        packet: stringify!($ident),
+                        field: "discriminant",
+                        source: Box::new(e),
+                    })?;
 
                 match discriminant_to_literal!($discriminant_type, discriminant) {
                     $(
                         $discriminant => {
                             $(
                                 $(
-                                    let $field = <$typ $(<$generics>)?>::read(buffer, version)
-                                        .context(concat!("failed to read field `", stringify!($field),
-                                            "` of enum `", stringify!($ident), "::", stringify!($variant), "`"))?
-                                            .into();
+                                    let $field = <$typ $(<$generics>)?>::read_with(buffer, limits)
+                                        .map_err(|e| $crate::net::Error::Field {
+                                            packet: concat!(stringify!($ident), "::", stringify!($variant)),
+                                            field: stringify!($field),
+                                            source: Box::new(e),
+                                        })?
+                                        .into();
                                 )*
                             )?
 
@@ -185,17 +230,16 @@ macro_rules! def_enum {
                             )?)
                         },
                     )*
-                    _ => Err(anyhow::anyhow!(
-                        concat!(
-                            "no discriminant for enum `", stringify!($ident), "` matched value {:?}"
-                        ), discriminant
-                    ))
+                    _ => Err($crate::net::Error::UnknownDiscriminant {
+                        enum_name: stringify!($ident),
+                        value: format!("{:?}", discriminant),
+                    })
                 }
             }
         }
 
         impl $crate::net::types::Encoder for $ident {
-            fn write_to(&self, buffer: &mut impl std::io::Write) -> anyhow::Result<()> {
+            fn write_to(&self, buffer: &mut impl std::io::Write) -> $crate::net::Result<()> {
                 match self {
                     $(
                         $ident::$variant $(
@@ -208,7 +252,7 @@ macro_rules! def_enum {
 
                             $(
                                 $(
-                                    user_type_convert_to_writeable!($typ $(<$generics>)?, $field).write(buffer, version)?;
+                                    user_type_convert_to_writeable!($typ $(<$generics>)?, $field).write_to(buffer)?;
                                 )*
                             )?
                         }
@@ -220,12 +264,21 @@ macro_rules! def_enum {
     };
 }
 
-/*
+/// Creates an enum of every packet in a protocol state and direction.
+/// The enum has to be called `Clientbound` or `Serverbound` since that is the direction of its packets.
+/// ```ignore
+/// packet_enum! {
+///     Clientbound(Login) {
+///         Disconnect,
+///         LoginSuccess,
+///     }
+/// }
+/// ```
 #[macro_export]
 macro_rules! packet_enum {
     (
-        $ident:ident {
-            $($id:literal = $packet:ident),* $(,)?
+        $ident:ident($state:ident) {
+            $($packet:ident),* $(,)?
         }
     ) => {
         #[derive(Debug, Clone)]
@@ -235,65 +288,59 @@ macro_rules! packet_enum {
             )*
         }
 
-        impl $ident {
-            /// Returns the packet ID of this packet.
-            pub fn id(&self) -> u32 {
+        impl $crate::net::packet::PacketEnum for $ident {
+            const STATE: $crate::net::packet::ProtocolState = $crate::net::packet::ProtocolState::$state;
+            const DIRECTION: $crate::net::packet::Direction = $crate::net::packet::Direction::$ident;
+
+            fn id(&self) -> $crate::net::types::VarInt {
+                use $crate::net::packet::Packet as _;
                 match self {
                     $(
-                        $ident::$packet(_) => $id,
+                        $ident::$packet(_) => $packet::ID,
                     )*
                 }
             }
-        }
 
-        impl crate::Readable for $ident {
-            fn read_from(buffer: &mut ::std::io::Cursor<&[u8]>, version: crate::ProtocolVersion) -> anyhow::Result<Self>
-            where
-                Self: Sized
-            {
-                let packet_id = VarInt::read(buffer, version)?.0;
-                match packet_id {
+            fn decode_with(
+                raw: $crate::net::packet::RawPacket,
+                limits: &$crate::net::codec::Limits,
+            ) -> $crate::net::Result<Self> {
+                use $crate::net::packet::Packet as _;
+                match raw.id {
                     $(
-                        id if id == $id => Ok($ident::$packet($packet::read(buffer, version)?)),
+                        id if id == $packet::ID => Ok($ident::$packet($packet::decode_with(raw, limits)?)),
                     )*
-                    _ => Err(anyhow::anyhow!("unknown packet ID {}", packet_id)),
+                    id => Err($crate::net::packet::UnknownPacketId {
+                        packet_enum: concat!(module_path!(), "::", stringify!($ident)),
+                        id,
+                        state: Self::STATE,
+                        direction: Self::DIRECTION,
+                    }
+                    .into()),
                 }
             }
-        }
 
-        impl crate::Writeable for $ident {
-            fn write_to(&self, buffer: &mut Vec<u8>, version: crate::ProtocolVersion) -> anyhow::Result<()> {
-                VarInt(self.id() as i32).write(buffer, version)?;
+            fn encode(&self) -> $crate::net::Result<$crate::net::packet::RawPacket> {
+                use $crate::net::packet::Packet as _;
                 match self {
                     $(
-                        $ident::$packet(packet) => {
-                            packet.write(buffer, version)?;
-                        }
+                        $ident::$packet(packet) => packet.encode(),
                     )*
                 }
-                Ok(())
             }
         }
 
         $(
-            impl VariantOf<$ident> for $packet {
-                fn discriminant_id() -> u32 { $id }
-
-                #[allow(unreachable_patterns)]
-                fn destructure(e: $ident) -> Option<Self> {
-                    match e {
-                        $ident::$packet(p) => Some(p),
-                        _ => None,
-                    }
-                }
-            }
-
             impl From<$packet> for $ident {
                 fn from(packet: $packet) -> Self {
                     $ident::$packet(packet)
                 }
             }
+
+            impl $crate::net::packet::StatePacket for $packet {
+                const STATE: $crate::net::packet::ProtocolState = $crate::net::packet::ProtocolState::$state;
+                const DIRECTION: $crate::net::packet::Direction = $crate::net::packet::Direction::$ident;
+            }
         )*
-    }
+    };
 }
-*/
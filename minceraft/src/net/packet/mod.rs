@@ -1,15 +1,12 @@
 mod builder;
-use super::types::*;
-use anyhow::{bail, Result};
-use flate2::{
-    bufread::{ZlibDecoder, ZlibEncoder},
-    Compression,
-};
-use std::{pin::Pin};
-use tokio::{
-    io::{AsyncRead, AsyncWrite /*AsyncReadExt, AsyncWriteExt*/},
-    task::spawn_blocking,
+use super::{
+    codec::{peek_var_int, var_int_len, Limits, PacketCodec},
+    types::*,
+    Error, Limit, Result,
 };
+use bytes::{Bytes, BytesMut};
+use std::pin::Pin;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 pub trait Packet: Encoder + Decoder {
     const ID: VarInt;
@@ -26,14 +23,163 @@ pub trait Packet: Encoder + Decoder {
         let mut buf = raw.data.as_slice();
         Self::read_from(&mut buf)
     }
+
+    /// Same as [`Packet::decode`] but with array fields bounded by `limits`, which is how connections decode.
+    fn decode_with(raw: RawPacket, limits: &Limits) -> Result<Self> {
+        let mut buf = raw.data.as_slice();
+        Self::read_with(&mut buf, limits)
+    }
+}
+
+/// The states of the protocol, the same packet id means something different in each one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProtocolState {
+    Handshaking,
+    Status,
+    Login,
+    Play,
+}
+
+/// The direction a packet is sent in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Clientbound,
+    Serverbound,
+}
+
+/// Which end of a connection we are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    Client,
+    Server,
+}
+
+impl Side {
+    /// The direction of the packets this side sends.
+    pub fn sends(self) -> Direction {
+        match self {
+            Side::Client => Direction::Serverbound,
+            Side::Server => Direction::Clientbound,
+        }
+    }
+
+    /// The direction of the packets this side receives.
+    pub fn receives(self) -> Direction {
+        match self {
+            Side::Client => Direction::Clientbound,
+            Side::Server => Direction::Serverbound,
+        }
+    }
 }
 
+/// A packet that belongs to a protocol state and direction, implemented by `packet_enum!`.
+pub trait StatePacket: Packet {
+    const STATE: ProtocolState;
+    const DIRECTION: Direction;
+}
+
+/// A `PacketEnum` holds every packet of one protocol state and direction.
+/// These are generated with the `packet_enum!` macro.
+pub trait PacketEnum: Sized {
+    const STATE: ProtocolState;
+    const DIRECTION: Direction;
+
+    fn id(&self) -> VarInt;
+
+    /// Decodes the packet that matches the id of `raw`.
+    /// Fails with [`UnknownPacketId`] if none of the packets have that id.
+    fn decode(raw: RawPacket) -> Result<Self> {
+        Self::decode_with(raw, &Limits::default())
+    }
+
+    /// Same as [`PacketEnum::decode`] but with array fields bounded by `limits`.
+    fn decode_with(raw: RawPacket, limits: &Limits) -> Result<Self>;
+
+    fn encode(&self) -> Result<RawPacket>;
+}
+
+/// The error returned by [`PacketEnum::decode`] when a packet id isn't part of the enum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownPacketId {
+    pub packet_enum: &'static str,
+    pub id: VarInt,
+    /// The state and direction of the enum, which is where `id` wasn't found.
+    pub state: ProtocolState,
+    pub direction: Direction,
+}
+
+impl std::fmt::Display for UnknownPacketId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "unknown {:?} packet id {:#04x} in the {:?} state for `{}`",
+            self.direction, self.id.0, self.state, self.packet_enum
+        )
+    }
+}
+
+impl std::error::Error for UnknownPacketId {}
+
 #[derive(Debug)]
 pub struct RawPacket {
     pub id: VarInt,
     pub data: Vec<u8>,
 }
 
+/// A packet that is framed and compressed for one compression threshold, so it can be sent
+/// to many connections without encoding it again. Clones share the frame.
+/// ```ignore
+/// let prepared = PreparedPacket::new(&movement.encode()?, threshold)?;
+/// for player in &mut players {
+///     player.conn.send_prepared(&prepared).await?;
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PreparedPacket {
+    id: VarInt,
+    threshold: i32,
+    frame: Bytes,
+    /// The length of the id and data before compression.
+    uncompressed_len: usize,
+}
+
+impl PreparedPacket {
+    /// Frames `packet` for connections with a compression threshold of `threshold`.
+    pub fn new(packet: &RawPacket, threshold: i32) -> Result<Self> {
+        Self::with_codec(packet, &mut PacketCodec::new(threshold))
+    }
+
+    /// Same as [`PreparedPacket::new`] but with the threshold and compressor of `codec`.
+    pub fn with_codec(packet: &RawPacket, codec: &mut PacketCodec) -> Result<Self> {
+        let mut frame = BytesMut::new();
+        codec.encode_frame(packet, &mut frame)?;
+        Ok(Self {
+            id: packet.id,
+            threshold: codec.threshold(),
+            frame: frame.freeze(),
+            uncompressed_len: var_int_len(packet.id.0 as u32 as usize) + packet.data.len(),
+        })
+    }
+
+    pub fn id(&self) -> VarInt {
+        self.id
+    }
+
+    /// The compression threshold the packet was framed for, it can only be sent to connections that use the same.
+    pub fn threshold(&self) -> i32 {
+        self.threshold
+    }
+
+    /// The frame including its length prefix, as it is sent before encryption.
+    pub fn frame(&self) -> &Bytes {
+        &self.frame
+    }
+
+    pub(crate) fn uncompressed_len(&self) -> usize {
+        self.uncompressed_len
+    }
+}
+
 impl AsyncWrite for RawPacket {
     fn poll_write(
         self: std::pin::Pin<&mut Self>,
@@ -65,116 +211,54 @@ impl AsyncWrite for RawPacket {
 }
 
 impl RawPacket {
+    /// Reads one packet from `r`. The length is read a byte at a time so `r` should be buffered.
+    /// Connections use [`PacketCodec`] on their own read buffer instead.
+    /// The frame is checked against the default [`Limits`](super::codec::Limits).
+    /// Legacy server list pings aren't packets and have to be caught before, see [`Conn::is_legacy_ping`](super::conn::Conn::is_legacy_ping).
     pub async fn unpack<T: AsyncRead + Unpin>(r: &mut T, threshold: i32) -> Result<Self> {
-        if threshold >= 0 {
-            Self::unpack_with_compression(r, threshold).await
-        } else {
-            Self::unpack_without_compression(r).await
-        }
+        Self::unpack_with(r, &mut PacketCodec::new(threshold)).await
     }
 
-    /// This reads the packet length and uses that to read the rest of the packet.
-    /// Works for both compressed and uncompressed packets.
-    async fn unpack_helper<T: AsyncRead + Unpin>(r: &mut T) -> Result<Vec<u8>> {
-        // Read the first four bytes asynchronously since a varint can be a maximum of four bytes
-        let mut first_four_bytes = [0u8; 4];
-        tokio::io::AsyncReadExt::read_exact(r, &mut first_four_bytes).await?;
-
-        // Spawn a thread that can block to read the packet length
-        let (remaining_bytes, len) = spawn_blocking(move || -> Result<(Vec<u8>, usize)> {
-            let mut first_four_bytes: &[u8] = first_four_bytes.as_slice();
-            let len = VarInt::read_from(&mut first_four_bytes)?.0 as usize; // read the varint from the first four bytes
-            Ok((first_four_bytes.into(), len)) // return the varint and the remaining bytes
-        })
-        .await??;
-
-        let mut buf = Vec::<u8>::new();
-
-        // Read the length of the packet - the remaining bytes asynchronously
-        let mut exact_buf = vec![0u8; len - remaining_bytes.len()];
-        tokio::io::AsyncReadExt::read_exact(r, &mut exact_buf).await?;
-
-        // Write the remaining bytes to a buffer
-        tokio::io::AsyncWriteExt::write_all(&mut buf, &remaining_bytes).await?;
-        // Write the rest to the buffer
-        tokio::io::AsyncWriteExt::write_all(&mut buf, &exact_buf).await?;
-        // The buffer should now hold all of the data
-        Ok(buf)
-    }
-
-    async fn unpack_without_compression<T: AsyncRead + Unpin>(r: &mut T) -> Result<Self> {
-        let buf = Self::unpack_helper(r).await?;
-        let (id, data) = spawn_blocking(move || -> Result<(VarInt, Vec<u8>)> {
-            let mut buf = buf.as_slice();
-            let id = VarInt::read_from(&mut buf)?; // Read the id
-            Ok((id, buf.into()))
-        })
-        .await??;
-
-        Ok(Self { id, data })
-        //let len = VarInt::read_from(r)?.0 as usize;
-        //let mut buf = vec![0; len];
-        //tokio::io::AsyncReadExt::read_exact(&mut r, &mut buf).await?;
-        //
-        //let mut buf = buf.as_slice();
-        //let id = VarInt::read_from(&mut buf)?;
-        //
-        //Ok(Self {
-        //    id,
-        //    data: buf.to_vec(),
-        //})
-    }
-
-    async fn unpack_with_compression<T: AsyncRead + Unpin>(
+    /// Same as [`RawPacket::unpack`] but with the threshold, limits and compressor of `codec`.
+    pub async fn unpack_with<T: AsyncRead + Unpin>(
         r: &mut T,
-        threshold: i32,
+        codec: &mut PacketCodec,
     ) -> Result<Self> {
-        let buf = Self::unpack_helper(r).await?;
-        // Spawn a thread that can block to do the unpacking
-        spawn_blocking(move || -> Result<RawPacket> {
-            let mut buf = buf.as_slice();
-            let data_len = VarInt::read_from(&mut buf)?.0;
-
-            let id: VarInt;
-
-            let data: Vec<u8>;
-
-            if data_len != 0 {
-                if data_len < threshold {
-                    bail!(
-                        "data length is smaller than threshold: {} < {}",
-                        data_len,
-                        threshold
-                    );
-                }
-
-                if data_len > 2097152 {
-                    bail!(
-                        "data length is larger than protocol maximum: {} > {}",
-                        data_len,
-                        2097152
-                    );
+        // A varint is at most five bytes, read it one byte at a time so short packets work too
+        let mut len_buf = [0u8; 5];
+        let mut read = 0;
+        let len = loop {
+            len_buf[read] = match r.read_u8().await {
+                Ok(b) => b,
+                // Only a stream that ends between packets is closed cleanly
+                Err(e) if read == 0 && e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    return Err(Error::Eof)
                 }
+                Err(e) => return Err(e.into()),
+            };
+            read += 1;
+            if let Some((len, _)) = peek_var_int(&len_buf[..read])? {
+                break len;
+            }
+        };
 
-                let mut decoder = ZlibDecoder::new(&buf[..]);
-                let buf = &mut Vec::new();
-
-                std::io::Read::read_to_end(&mut decoder, buf)?;
-
-                let mut buf = buf.as_slice();
-
-                id = VarInt::read_from(&mut buf)?;
+        if len < 0 {
+            return Err(Error::Invalid(format!("packet length is negative: {}", len)));
+        }
 
-                data = buf.to_vec();
-            } else {
-                id = VarInt::read_from(&mut buf)?;
+        let max = codec.limits().max_frame_length;
+        if len as usize > max {
+            return Err(Error::TooLarge {
+                limit: Limit::Frame,
+                len: len as usize,
+                max,
+            });
+        }
 
-                data = buf.to_vec();
-            }
+        let mut frame = vec![0u8; len as usize];
+        r.read_exact(&mut frame).await?;
 
-            Ok(Self { id, data })
-        })
-        .await?
+        codec.decode_frame(&frame)
     }
 
     pub async fn pack<T: AsyncWrite + Unpin>(
@@ -182,67 +266,19 @@ impl RawPacket {
         w: &mut T,
         threshold: i32,
     ) -> Result<()> {
-        if threshold >= 0 {
-            self.pack_with_compression(w, threshold).await
-        } else {
-            self.pack_without_compression(w).await
-        }
+        self.pack_with(w, &mut PacketCodec::new(threshold)).await
     }
 
-    async fn pack_with_compression<T: AsyncWrite + Unpin>(
+    /// Same as [`RawPacket::pack`] but with the threshold and compressor of `codec`,
+    /// e.g. to compress with another level.
+    pub async fn pack_with<T: AsyncWrite + Unpin>(
         self,
         w: &mut T,
-        threshold: i32,
+        codec: &mut PacketCodec,
     ) -> Result<()> {
-        let wb = spawn_blocking(move || -> Result<Vec<u8>> {
-            let mut buf = Vec::new();
-            self.id.write_to(&mut buf)?;
-            let mut wb = Vec::<u8>::new();
-            std::io::Write::write_all(&mut buf, &self.data)?;
-            let data_len = buf.len();
-
-            if data_len < threshold as usize {
-                let mut buf2 = Vec::new();
-                VarInt(0).write_to(&mut buf2)?;
-                std::io::Write::write_all(&mut buf2, &buf)?;
-
-                VarInt(buf2.len() as i32).write_to(&mut wb)?;
-                std::io::Write::write_all(&mut wb, &buf2)?;
-                Ok(wb)
-            } else {
-                let mut encoder = ZlibEncoder::new(buf.as_slice(), Compression::default());
-                let mut buf2 = Vec::new();
-                VarInt(data_len as i32).write_to(&mut buf2)?;
-                std::io::Read::read_to_end(&mut encoder, &mut buf2)?;
-                VarInt(buf2.len() as i32).write_to(&mut wb)?;
-                std::io::Write::write_all(&mut wb, &buf2)?;
-                Ok(wb)
-            }
-        })
-        .await??;
-        tokio::io::AsyncWriteExt::write_all(w, &wb).await?;
-
-        Ok(())
-    }
-
-    async fn pack_without_compression<T: AsyncWrite + Unpin>(
-        self,
-        w: &mut T,
-    ) -> Result<()> {
-        let wb = spawn_blocking(move || -> Result<Vec<u8>> {
-            let mut buf = Vec::new();
-            let mut wb = Vec::new();
-
-            self.id.write_to(&mut buf)?;
-            std::io::Write::write_all(&mut buf, self.data.as_slice())?;
-
-            VarInt(buf.len() as i32).write_to(&mut wb)?;
-            std::io::Write::write_all(&mut wb, &buf)?;
-
-            Ok(wb)
-        })
-        .await??;
-        tokio::io::AsyncWriteExt::write_all(w, &wb).await?;
+        let mut wb = BytesMut::new();
+        codec.encode_frame(&self, &mut wb)?;
+        w.write_all(&wb).await?;
 
         Ok(())
     }
@@ -0,0 +1,420 @@
+//! Sitting between a client and a server to watch or change what they send each other.
+//! ```ignore
+//! let proxy = Proxy::new("127.0.0.1:25565");
+//! let listener = TcpListener::bind("127.0.0.1:25566").await?;
+//! loop {
+//!     let (stream, _) = listener.accept().await?;
+//!     let proxy = proxy.clone();
+//!     tokio::spawn(async move {
+//!         let hook = |direction, state, packet: RawPacket, _: &mut Inject| {
+//!             println!("{:?} {:?} {:?}", direction, state, packet.id);
+//!             Some(packet)
+//!         };
+//!         if let Err(e) = proxy.proxy(stream, hook).await {
+//!             eprintln!("{}", e);
+//!         }
+//!     });
+//! }
+//! ```
+use super::{
+    conn::{Conn, ConnReader, ConnWriter},
+    packet::{Direction, Packet, ProtocolState, RawPacket, Side},
+    server::Keypair,
+    types::Decoder,
+    Error, Result,
+};
+use crate::p47::{enums::HandshakeState, handshaking, login, play};
+use rand::Rng;
+use rsa::{pkcs8::DecodePublicKey, Pkcs1v15Encrypt, RsaPublicKey};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+    sync::{Mutex, OnceCell},
+};
+
+/// How long the handshake and login through the proxy may take by default.
+pub const DEFAULT_PROXY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Forwards clients to a server.
+#[derive(Debug, Clone)]
+pub struct Proxy {
+    /// The address of the server as `host:port`.
+    pub upstream: String,
+    /// How long the handshake and login may take before they fail with
+    /// [`Error::Timeout`](super::Error::Timeout), `None` waits forever.
+    pub timeout: Option<Duration>,
+    /// The keypair the client leg is encrypted with, which is only generated once a server asks for encryption.
+    keypair: Arc<OnceCell<Keypair>>,
+}
+
+/// Sees every packet that passes through a [`Proxy`].
+/// Closures with the same arguments as [`Hook::packet`] are hooks as well.
+pub trait Hook: Send {
+    /// Called with each packet before it is forwarded in `direction`, `state` is the state it was read in.
+    /// Returns the packet to forward, which may have been changed, or `None` to drop it.
+    /// Packets pushed to `inject` are sent after it.
+    ///
+    /// State changes and compression thresholds are mirrored on each leg as that leg sees them:
+    /// the leg a packet came from follows the packet as it was read,
+    /// the leg it goes to follows what is actually sent there, including injected packets.
+    fn packet(
+        &mut self,
+        direction: Direction,
+        state: ProtocolState,
+        packet: RawPacket,
+        inject: &mut Inject,
+    ) -> Option<RawPacket>;
+}
+
+impl<F> Hook for F
+where
+    F: FnMut(Direction, ProtocolState, RawPacket, &mut Inject) -> Option<RawPacket> + Send,
+{
+    fn packet(
+        &mut self,
+        direction: Direction,
+        state: ProtocolState,
+        packet: RawPacket,
+        inject: &mut Inject,
+    ) -> Option<RawPacket> {
+        self(direction, state, packet, inject)
+    }
+}
+
+/// The packets a [`Hook`] sends on its own.
+#[derive(Debug, Default)]
+pub struct Inject {
+    packets: Vec<(Direction, RawPacket)>,
+}
+
+impl Inject {
+    /// Sends `packet` to the client if `direction` is clientbound and to the server otherwise.
+    pub fn push(&mut self, direction: Direction, packet: RawPacket) {
+        self.packets.push((direction, packet));
+    }
+}
+
+/// A change a packet makes to the connection it is sent over.
+enum Transition {
+    State(ProtocolState),
+    Threshold(i32),
+}
+
+/// The writing halves of both legs and the hook, which both directions share.
+struct Legs<CW, SW, H> {
+    client: Mutex<ConnWriter<CW>>,
+    server: Mutex<ConnWriter<SW>>,
+    hook: std::sync::Mutex<H>,
+}
+
+impl Proxy {
+    pub fn new(upstream: impl Into<String>) -> Self {
+        Self {
+            upstream: upstream.into(),
+            timeout: Some(DEFAULT_PROXY_TIMEOUT),
+            keypair: Arc::new(OnceCell::new()),
+        }
+    }
+
+    /// Connects a freshly accepted client to the upstream server and forwards packets
+    /// until one of them closes the connection.
+    pub async fn proxy(&self, client: TcpStream, hook: impl Hook) -> Result<()> {
+        let client = Conn::try_from(client)?;
+        let server = match self.timeout {
+            Some(timeout) => Conn::connect_timeout(self.upstream.as_str(), timeout).await?,
+            None => Conn::connect(self.upstream.as_str()).await?,
+        };
+        self.proxy_conn(client, server, hook).await
+    }
+
+    /// Same as [`Proxy::proxy`] but for any connections, `server` has to be connected to the upstream server already.
+    pub async fn proxy_conn<CR, CW, SR, SW>(
+        &self,
+        mut client: Conn<CR, CW>,
+        mut server: Conn<SR, SW>,
+        hook: impl Hook,
+    ) -> Result<()>
+    where
+        CR: AsyncRead + Unpin,
+        CW: AsyncWrite + Unpin,
+        SR: AsyncRead + Unpin,
+        SW: AsyncWrite + Unpin,
+    {
+        // The proxy is the server of the client and the client of the server
+        client.set_side(Side::Server);
+        server.set_side(Side::Client);
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        client.set_deadline(deadline);
+        server.set_deadline(deadline);
+
+        let (mut client_reader, client_writer) = client.into_split();
+        let (mut server_reader, server_writer) = server.into_split();
+        let legs = Legs {
+            client: Mutex::new(client_writer),
+            server: Mutex::new(server_writer),
+            hook: std::sync::Mutex::new(hook),
+        };
+
+        let handshake = client_reader.read_packet().await?;
+        legs.pass(&mut client_reader, Direction::Serverbound, handshake)
+            .await?;
+        if client_reader.state() == ProtocolState::Login
+            && !self
+                .login(&mut client_reader, &mut server_reader, &legs)
+                .await?
+        {
+            legs.shutdown().await;
+            return Ok(());
+        }
+
+        client_reader.set_deadline(None);
+        server_reader.set_deadline(None);
+        legs.client.lock().await.set_deadline(None);
+        legs.server.lock().await.set_deadline(None);
+
+        // Either side closing ends the proxy, whatever the other side still sends is dropped
+        let result = tokio::select! {
+            result = legs.forward(client_reader, Direction::Serverbound) => result,
+            result = legs.forward(server_reader, Direction::Clientbound) => result,
+        };
+        legs.shutdown().await;
+        match result {
+            Err(e) if e.is_eof() => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Passes packets back and forth until the server leg is in the play state.
+    /// Returns false if the server disconnected the client instead.
+    async fn login<CR, CW, SR, SW, H>(
+        &self,
+        client_reader: &mut ConnReader<CR>,
+        server_reader: &mut ConnReader<SR>,
+        legs: &Legs<CW, SW, H>,
+    ) -> Result<bool>
+    where
+        CR: AsyncRead + Unpin,
+        CW: AsyncWrite + Unpin,
+        SR: AsyncRead + Unpin,
+        SW: AsyncWrite + Unpin,
+        H: Hook,
+    {
+        let login_start = client_reader.read_packet().await?;
+        legs.pass(client_reader, Direction::Serverbound, login_start)
+            .await?;
+
+        // Clients don't send anything else until they are asked for encryption or logged in
+        while server_reader.state() == ProtocolState::Login {
+            let packet = server_reader.read_packet().await?;
+            if packet.id == login::clientbound::EncryptionRequest::ID {
+                self.encrypt(client_reader, server_reader, legs, packet)
+                    .await?;
+                continue;
+            }
+            let disconnect = packet.id == login::clientbound::Disconnect::ID;
+            legs.pass(server_reader, Direction::Clientbound, packet)
+                .await?;
+            if disconnect {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Does the encryption handshake with the client as if the proxy was the server and then answers
+    /// the `request` of the server with another shared secret, so each leg is encrypted with its own key.
+    /// Neither side talks to the session server, so this only works with offline mode servers.
+    /// The hook doesn't see the encryption packets.
+    async fn encrypt<CR, CW, SR, SW, H>(
+        &self,
+        client_reader: &mut ConnReader<CR>,
+        server_reader: &mut ConnReader<SR>,
+        legs: &Legs<CW, SW, H>,
+        request: RawPacket,
+    ) -> Result<()>
+    where
+        CR: AsyncRead + Unpin,
+        CW: AsyncWrite + Unpin,
+        SR: AsyncRead + Unpin,
+        SW: AsyncWrite + Unpin,
+    {
+        let request = login::clientbound::EncryptionRequest::decode(request)?;
+        let keypair = self
+            .keypair
+            .get_or_try_init(|| async {
+                tokio::task::spawn_blocking(Keypair::generate)
+                    .await
+                    .map_err(std::io::Error::from)?
+            })
+            .await?;
+
+        let verify_token = rand::thread_rng().gen::<[u8; 4]>();
+        let mut client_writer = legs.client.lock().await;
+        client_writer
+            .send_packet(login::clientbound::EncryptionRequest {
+                server_id: request.server_id,
+                public_key: keypair.public_key().to_vec(),
+                verify_token: verify_token.to_vec(),
+            })
+            .await?;
+        let response = match client_reader.read_typed().await? {
+            login::Serverbound::EncryptionResponse(p) => p,
+            login::Serverbound::LoginStart(_) => {
+                return Err(Error::Invalid(
+                    "expected EncryptionResponse but got LoginStart".to_owned(),
+                ))
+            }
+        };
+        if keypair.decrypt(&response.verify_token)? != verify_token {
+            return Err(Error::Auth("verify token doesn't match".to_owned()));
+        }
+        let shared_secret = keypair.decrypt(&response.shared_secret)?;
+        client_reader.enable_encryption(&shared_secret)?;
+        client_writer.enable_encryption(&shared_secret)?;
+
+        let shared_secret = rand::thread_rng().gen::<[u8; 16]>();
+        let key = RsaPublicKey::from_public_key_der(&request.public_key)?;
+        let response = {
+            let mut rng = rand::thread_rng();
+            login::serverbound::EncryptionResponse {
+                shared_secret: key.encrypt(&mut rng, Pkcs1v15Encrypt, &shared_secret)?,
+                verify_token: key.encrypt(&mut rng, Pkcs1v15Encrypt, &request.verify_token)?,
+            }
+        };
+        let mut server_writer = legs.server.lock().await;
+        server_writer.send_packet(response).await?;
+        server_reader.enable_encryption(&shared_secret)?;
+        server_writer.enable_encryption(&shared_secret)?;
+        Ok(())
+    }
+}
+
+impl<CW, SW, H> Legs<CW, SW, H>
+where
+    CW: AsyncWrite + Unpin,
+    SW: AsyncWrite + Unpin,
+    H: Hook,
+{
+    /// Passes on everything `reader` reads until it fails.
+    async fn forward<R: AsyncRead + Unpin>(
+        &self,
+        mut reader: ConnReader<R>,
+        direction: Direction,
+    ) -> Result<()> {
+        loop {
+            let packet = reader.read_packet().await?;
+            self.pass(&mut reader, direction, packet).await?;
+        }
+    }
+
+    /// Applies a packet that was read to its leg, runs the hook on it and sends what it returns.
+    async fn pass<R>(
+        &self,
+        reader: &mut ConnReader<R>,
+        direction: Direction,
+        packet: RawPacket,
+    ) -> Result<()>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let state = reader.state();
+        match transition(direction, state, &packet) {
+            Some(Transition::State(state)) => reader.set_state(state),
+            Some(Transition::Threshold(threshold)) => reader.set_compression_threshhold(threshold),
+            None => {}
+        }
+
+        let mut inject = Inject::default();
+        let packet = self
+            .hook
+            .lock()
+            .unwrap()
+            .packet(direction, state, packet, &mut inject);
+        for (direction, packet) in packet
+            .map(|p| (direction, p))
+            .into_iter()
+            .chain(inject.packets)
+        {
+            match direction {
+                Direction::Clientbound => {
+                    send(&mut *self.client.lock().await, direction, packet).await?
+                }
+                Direction::Serverbound => {
+                    send(&mut *self.server.lock().await, direction, packet).await?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Closes both legs, errors don't matter anymore at this point.
+    async fn shutdown(&self) {
+        let _ = self.client.lock().await.shutdown().await;
+        let _ = self.server.lock().await.shutdown().await;
+    }
+}
+
+/// Sends a packet and applies it to the leg it is sent over.
+async fn send<W: AsyncWrite + Unpin>(
+    writer: &mut ConnWriter<W>,
+    direction: Direction,
+    packet: RawPacket,
+) -> Result<()> {
+    let transition = transition(direction, writer.state(), &packet);
+    writer.send_raw_packet(packet).await?;
+    match transition {
+        Some(Transition::State(state)) => writer.set_state(state),
+        Some(Transition::Threshold(threshold)) => writer.set_compression_threshhold(threshold),
+        None => {}
+    }
+    Ok(())
+}
+
+/// How `packet` changes the connection it is sent over, packets that don't decode change nothing.
+/// It runs twice for every forwarded packet, so the data is decoded in place and only for the few ids that matter.
+fn transition(
+    direction: Direction,
+    state: ProtocolState,
+    packet: &RawPacket,
+) -> Option<Transition> {
+    let mut data = packet.data.as_slice();
+    match (direction, state) {
+        (Direction::Serverbound, ProtocolState::Handshaking)
+            if packet.id == handshaking::serverbound::Handshake::ID =>
+        {
+            match handshaking::serverbound::Handshake::read_from(&mut data)
+                .ok()?
+                .next_state
+            {
+                HandshakeState::Status => Some(Transition::State(ProtocolState::Status)),
+                HandshakeState::Login => Some(Transition::State(ProtocolState::Login)),
+            }
+        }
+        (Direction::Clientbound, ProtocolState::Login)
+            if packet.id == login::clientbound::SetCompression::ID =>
+        {
+            let threshold = login::clientbound::SetCompression::read_from(&mut data)
+                .ok()?
+                .threshold;
+            Some(Transition::Threshold(threshold))
+        }
+        (Direction::Clientbound, ProtocolState::Login)
+            if packet.id == login::clientbound::LoginSuccess::ID =>
+        {
+            Some(Transition::State(ProtocolState::Play))
+        }
+        (Direction::Clientbound, ProtocolState::Play)
+            if packet.id == play::clientbound::SetCompression::ID =>
+        {
+            let threshold = play::clientbound::SetCompression::read_from(&mut data)
+                .ok()?
+                .threshold;
+            Some(Transition::Threshold(threshold))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,414 @@
+//! Recording the packets of a connection to disk and replaying them, so a misbehaving bot can be reproduced offline.
+//! ```ignore
+//! let mut conn = Recorder::create(conn, "session.mcrec").await?;
+//! run_bot(&mut conn).await?;
+//! conn.finish().await?;
+//!
+//! // Later, ten times as fast
+//! let recording = Recording::open("session.mcrec").await?;
+//! let mut conn = replay(recording, ReplayOptions { speed: 10.0 })?;
+//! run_bot(&mut conn).await?;
+//! ```
+//! A recording starts with [`MAGIC`], the [`VERSION`] and the recorded [`Side`], followed by
+//! [`Record`]s which are each prefixed by their length as a VarInt.
+use super::{
+    codec::{peek_var_int, MAX_DATA_LENGTH},
+    conn::{check_packet, Conn},
+    packet::{Direction, PacketEnum, ProtocolState, RawPacket, Side, StatePacket},
+    types::{raw, Decoder, Encoder, VarInt, VarLong},
+    Error, Result,
+};
+use std::{
+    io,
+    path::Path,
+    time::{Duration, Instant},
+};
+use tokio::{
+    fs::File,
+    io::{
+        AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter, DuplexStream,
+        ReadHalf, WriteHalf,
+    },
+    net::tcp::{OwnedReadHalf, OwnedWriteHalf},
+};
+
+/// The bytes every recording starts with.
+pub const MAGIC: [u8; 4] = *b"MCRC";
+
+/// The version of the format that is written.
+pub const VERSION: u8 = 1;
+
+/// The longest record that is read, a packet of [`MAX_DATA_LENGTH`] plus the fields in front of it.
+const MAX_RECORD_LENGTH: usize = MAX_DATA_LENGTH as usize + 32;
+
+/// A packet that was sent or received, after decryption and decompression.
+#[derive(Debug)]
+pub struct Record {
+    /// The time since the recording started.
+    pub time: Duration,
+    pub direction: Direction,
+    /// The state the connection was in when the packet was sent or received.
+    pub state: ProtocolState,
+    pub packet: RawPacket,
+}
+
+impl Encoder for Record {
+    fn write_to(&self, w: &mut impl io::Write) -> Result<()> {
+        VarLong(self.time.as_micros() as i64).write_to(w)?;
+        let direction: u8 = match self.direction {
+            Direction::Clientbound => 0,
+            Direction::Serverbound => 1,
+        };
+        direction.write_to(w)?;
+        let state: u8 = match self.state {
+            ProtocolState::Handshaking => 0,
+            ProtocolState::Status => 1,
+            ProtocolState::Login => 2,
+            ProtocolState::Play => 3,
+        };
+        state.write_to(w)?;
+        self.packet.id.write_to(w)?;
+        raw::write_byte_array(w, &self.packet.data)
+    }
+}
+
+impl Decoder for Record {
+    fn read_from(r: &mut impl io::Read) -> Result<Self> {
+        let time = VarLong::read_from(r)?.0;
+        let direction = match u8::read_from(r)? {
+            0 => Direction::Clientbound,
+            1 => Direction::Serverbound,
+            v => {
+                return Err(Error::Invalid(format!(
+                    "invalid direction in record: {}",
+                    v
+                )))
+            }
+        };
+        let state = match u8::read_from(r)? {
+            0 => ProtocolState::Handshaking,
+            1 => ProtocolState::Status,
+            2 => ProtocolState::Login,
+            3 => ProtocolState::Play,
+            v => return Err(Error::Invalid(format!("invalid state in record: {}", v))),
+        };
+        let id = VarInt::read_from(r)?;
+        // Packets can be larger than the array limit, so this is only bounded by the record
+        let len = raw::read_length(r)?;
+        let data = raw::read_bytes(r, len)?;
+        Ok(Self {
+            time: Duration::from_micros(time.max(0) as u64),
+            direction,
+            state,
+            packet: RawPacket { id, data },
+        })
+    }
+}
+
+/// Wraps a [`Conn`] and records every packet that is sent or read through it.
+/// Packets sent or read through [`Recorder::conn_mut`] aren't recorded, and neither are keep alives
+/// that the connection handles itself.
+pub struct Recorder<R = OwnedReadHalf, W = OwnedWriteHalf, O = BufWriter<File>> {
+    conn: Conn<R, W>,
+    out: O,
+    side: Side,
+    start: Instant,
+}
+
+impl<R, W> Recorder<R, W>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    /// Records `conn` into a new file at `path`, an existing file is overwritten.
+    pub async fn create(conn: Conn<R, W>, path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::create(path).await?;
+        Self::new(conn, BufWriter::new(file)).await
+    }
+}
+
+impl<R, W, O> Recorder<R, W, O>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+    O: AsyncWrite + Unpin,
+{
+    /// Records `conn` into `out`, which fails if the side of `conn` isn't known.
+    pub async fn new(conn: Conn<R, W>, mut out: O) -> Result<Self> {
+        let side = conn.side().ok_or_else(|| {
+            Error::Invalid("the side of a connection has to be known to record it".to_owned())
+        })?;
+        let mut header = MAGIC.to_vec();
+        header.push(VERSION);
+        header.push(match side {
+            Side::Client => 0,
+            Side::Server => 1,
+        });
+        out.write_all(&header).await?;
+        Ok(Self {
+            conn,
+            out,
+            side,
+            start: Instant::now(),
+        })
+    }
+
+    /// Sends and records a packet, see [`Conn::send_packet`].
+    pub async fn send_packet<T: StatePacket>(&mut self, packet: T) -> Result<()> {
+        let direction = self.conn.side().map(Side::sends);
+        check_packet::<T>(self.conn.state(), direction, T::STATE, T::DIRECTION)?;
+        self.send_raw_packet(packet.encode()?).await
+    }
+
+    /// Sends and records a raw packet, see [`Conn::send_raw_packet`].
+    pub async fn send_raw_packet(&mut self, packet: RawPacket) -> Result<()> {
+        let record = Record {
+            time: self.start.elapsed(),
+            direction: self.side.sends(),
+            state: self.conn.state(),
+            packet,
+        };
+        let frame = frame_record(&record)?;
+        self.conn.send_raw_packet(record.packet).await?;
+        self.out.write_all(&frame).await?;
+        Ok(())
+    }
+
+    /// Reads and records a packet, see [`Conn::read_packet`].
+    pub async fn read_packet(&mut self) -> Result<RawPacket> {
+        let state = self.conn.state();
+        let packet = self.conn.read_packet().await?;
+        let record = Record {
+            time: self.start.elapsed(),
+            direction: self.side.receives(),
+            state,
+            packet,
+        };
+        self.out.write_all(&frame_record(&record)?).await?;
+        Ok(record.packet)
+    }
+
+    /// Reads and records a packet and decodes it as one of the packets in `E`, see [`Conn::read_typed`].
+    pub async fn read_typed<E: PacketEnum>(&mut self) -> Result<E> {
+        let direction = self.conn.side().map(Side::receives);
+        check_packet::<E>(self.conn.state(), direction, E::STATE, E::DIRECTION)?;
+        let packet = self.read_packet().await?;
+        E::decode_with(packet, &self.conn.limits())
+    }
+
+    /// The recorded connection, e.g. to change its state or compression threshold.
+    pub fn conn(&self) -> &Conn<R, W> {
+        &self.conn
+    }
+
+    pub fn conn_mut(&mut self) -> &mut Conn<R, W> {
+        &mut self.conn
+    }
+
+    /// Flushes the connection and the recording.
+    pub async fn flush(&mut self) -> Result<()> {
+        self.conn.flush().await?;
+        self.out.flush().await?;
+        Ok(())
+    }
+
+    /// Flushes the recording and returns the connection and where it was recorded to.
+    pub async fn finish(mut self) -> Result<(Conn<R, W>, O)> {
+        self.out.flush().await?;
+        Ok((self.conn, self.out))
+    }
+}
+
+/// Encodes `record` with its length prefix.
+fn frame_record(record: &Record) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    record.write_to(&mut body)?;
+    let mut frame = Vec::with_capacity(body.len() + 5);
+    VarInt::from(body.len()).write_to(&mut frame)?;
+    frame.extend_from_slice(&body);
+    Ok(frame)
+}
+
+/// Reads the records of a recording one by one.
+pub struct Recording<I = BufReader<File>> {
+    input: I,
+    side: Side,
+}
+
+impl Recording {
+    /// Opens the recording at `path`.
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path).await?;
+        Self::new(BufReader::new(file)).await
+    }
+}
+
+impl<I: AsyncRead + Unpin> Recording<I> {
+    /// Reads the header of the recording in `input`.
+    pub async fn new(mut input: I) -> Result<Self> {
+        let mut header = [0u8; 6];
+        input.read_exact(&mut header).await?;
+        if header[..4] != MAGIC {
+            return Err(Error::Invalid("not a recording".to_owned()));
+        }
+        if header[4] != VERSION {
+            return Err(Error::Invalid(format!(
+                "unsupported recording version: {}",
+                header[4]
+            )));
+        }
+        let side = match header[5] {
+            0 => Side::Client,
+            1 => Side::Server,
+            v => return Err(Error::Invalid(format!("invalid side in recording: {}", v))),
+        };
+        Ok(Self { input, side })
+    }
+
+    /// The side of the connection that was recorded.
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    /// Reads the next record, `None` if the recording ended.
+    pub async fn next_record(&mut self) -> Result<Option<Record>> {
+        // The length is read a byte at a time, which is why `input` should be buffered
+        let mut len_buf = [0u8; 5];
+        let mut read = 0;
+        let len = loop {
+            len_buf[read] = match self.input.read_u8().await {
+                Ok(b) => b,
+                Err(e) if read == 0 && e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            read += 1;
+            if let Some((len, _)) = peek_var_int(&len_buf[..read])? {
+                break len;
+            }
+        };
+        let len = usize::try_from(len)
+            .map_err(|_| Error::Invalid(format!("record length is negative: {}", len)))?;
+        if len > MAX_RECORD_LENGTH {
+            return Err(Error::Invalid(format!(
+                "record is too long: {} > {}",
+                len, MAX_RECORD_LENGTH
+            )));
+        }
+
+        // A truncated recording ends early instead of making this allocate all of `len` up front
+        let mut body = Vec::new();
+        (&mut self.input)
+            .take(len as u64)
+            .read_to_end(&mut body)
+            .await?;
+        if body.len() != len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Record::read_from(&mut body.as_slice()).map(Some)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReplayOptions {
+    /// How many times as fast as recorded the packets are replayed, which has to be above 0.
+    /// `f64::INFINITY` replays them as fast as they are read.
+    pub speed: f64,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        Self { speed: 1.0 }
+    }
+}
+
+/// Replays what the recorded side received into a connection in memory, which is returned
+/// as the recorded side in the handshaking state. What is sent on it is ignored,
+/// and it is closed once the recording ends.
+/// The compression threshold follows the `SetCompression` packets of the recording,
+/// but encrypted logins can't be replayed since the shared secret isn't recorded.
+/// Fails with [`Error::Invalid`] if the speed isn't above 0.
+pub fn replay<I>(
+    mut recording: Recording<I>,
+    options: ReplayOptions,
+) -> Result<Conn<ReadHalf<DuplexStream>, WriteHalf<DuplexStream>>>
+where
+    I: AsyncRead + Unpin + Send + 'static,
+{
+    if options.speed.is_nan() || options.speed <= 0.0 {
+        return Err(Error::Invalid(format!(
+            "replay speed has to be above 0: {}",
+            options.speed
+        )));
+    }
+    let side = recording.side();
+    let (client, server) = Conn::pair();
+    let (conn, other) = match side {
+        Side::Client => (client, server),
+        Side::Server => (server, client),
+    };
+
+    let (mut reader, mut writer) = other.into_split();
+    tokio::spawn(async move {
+        // Whatever the connection sends has to be read so it doesn't fill up the pipe
+        let _ = tokio::io::copy(&mut reader, &mut tokio::io::sink()).await;
+    });
+    tokio::spawn(async move {
+        let start = Instant::now();
+        while let Ok(Some(record)) = recording.next_record().await {
+            if record.direction != side.receives() {
+                continue;
+            }
+            // A speed close to 0 puts the record further out than an `Instant` goes, so it never comes
+            let delay = Duration::try_from_secs_f64(record.time.as_secs_f64() / options.speed);
+            let Some(at) = delay.ok().and_then(|delay| start.checked_add(delay)) else {
+                break;
+            };
+            tokio::time::sleep_until(at.into()).await;
+
+            let threshold = compression_threshold(&record);
+            if writer.send_raw_packet(record.packet).await.is_err() {
+                break;
+            }
+            if let Some(threshold) = threshold {
+                writer.set_compression_threshhold(threshold);
+            }
+        }
+        // The read half of the pipe is still draining, so only a shutdown makes the end read as `Eof`
+        let _ = writer.shutdown().await;
+    });
+
+    Ok(conn)
+}
+
+/// The threshold a `SetCompression` record switches to, `None` for any other record.
+#[cfg(feature = "p47")]
+fn compression_threshold(record: &Record) -> Option<i32> {
+    use super::packet::Packet;
+    use crate::p47::{login, play};
+
+    let packet = &record.packet;
+    let mut data = packet.data.as_slice();
+    match (record.direction, record.state) {
+        (Direction::Clientbound, ProtocolState::Login)
+            if packet.id == login::clientbound::SetCompression::ID =>
+        {
+            login::clientbound::SetCompression::read_from(&mut data)
+                .ok()
+                .map(|p| p.threshold)
+        }
+        (Direction::Clientbound, ProtocolState::Play)
+            if packet.id == play::clientbound::SetCompression::ID =>
+        {
+            play::clientbound::SetCompression::read_from(&mut data)
+                .ok()
+                .map(|p| p.threshold)
+        }
+        _ => None,
+    }
+}
+
+/// Without the packet definitions there is no way to tell which packet sets the threshold.
+#[cfg(not(feature = "p47"))]
+fn compression_threshold(_record: &Record) -> Option<i32> {
+    None
+}
@@ -0,0 +1,344 @@
+//! Accepting clients on the server side.
+//! ```ignore
+//! let server = Server::new(r#"{"version":{"name":"1.8.9","protocol":47},"players":{"max":20,"online":0},"description":{"text":"hi"}}"#);
+//! let listener = TcpListener::bind("0.0.0.0:25565").await?;
+//! loop {
+//!     let (stream, _) = listener.accept().await?;
+//!     if let Accepted::Login(player) = server.accept(stream).await? {
+//!         println!("{} joined with uuid {}", player.name, player.uuid);
+//!     }
+//! }
+//! ```
+#[cfg(feature = "online")]
+use super::session::{self, GameProfile, ProfileProperty, DEFAULT_SESSION_URL};
+use super::{
+    conn::Conn,
+    legacy::{self, LegacyStatus},
+    packet::{ProtocolState, Side},
+    types::Uuid,
+    Error, Result,
+};
+use crate::p47::{enums::HandshakeState, handshaking, login, status, PROTOCOL_VERSION};
+use md5::{Digest, Md5};
+use std::time::{Duration, Instant};
+#[cfg(feature = "online")]
+use rand::Rng;
+use rsa::{pkcs8::EncodePublicKey, Pkcs1v15Encrypt, RsaPrivateKey};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream,
+    },
+};
+
+/// The compression threshold vanilla servers use by default.
+pub const DEFAULT_COMPRESSION_THRESHOLD: i32 = 256;
+
+/// How long clients have for the handshake, status and login by default.
+pub const DEFAULT_ACCEPT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The settings used for every accepted client.
+#[derive(Debug, Clone)]
+pub struct Server {
+    /// The status JSON sent to clients in the server list.
+    /// Clients before 1.7 get the version, player counts and description out of it, see [`LegacyStatus::from_json`].
+    pub status: String,
+    /// Packets at least this big are compressed, a negative threshold disables compression.
+    pub compression_threshold: i32,
+    /// How long a client has for the handshake, status and login before it fails with
+    /// [`Error::Timeout`](super::Error::Timeout), `None` waits forever.
+    pub timeout: Option<Duration>,
+    /// Authenticates players with the session server if set, otherwise they get an offline uuid.
+    #[cfg(feature = "online")]
+    pub online_mode: Option<OnlineMode>,
+}
+
+/// The keypair and session server used to authenticate players.
+#[cfg(feature = "online")]
+#[derive(Debug, Clone)]
+pub struct OnlineMode {
+    keypair: Keypair,
+    /// The base url of the session server, this is [`DEFAULT_SESSION_URL`] unless you want to test against your own.
+    pub session_url: String,
+    pub http: reqwest::Client,
+}
+
+/// What a client did after the handshake.
+pub enum Accepted<R = OwnedReadHalf, W = OwnedWriteHalf> {
+    /// The client requested the status and the connection is finished, this includes legacy pings.
+    Status,
+    /// The client logged in and is now in the play state.
+    Login(Box<Player<R, W>>),
+}
+
+/// A client that finished logging in.
+pub struct Player<R = OwnedReadHalf, W = OwnedWriteHalf> {
+    pub conn: Conn<R, W>,
+    pub name: String,
+    pub uuid: Uuid,
+    /// The profile properties from the session server, these are empty in offline mode.
+    #[cfg(feature = "online")]
+    pub properties: Vec<ProfileProperty>,
+}
+
+impl Server {
+    pub fn new(status: impl Into<String>) -> Self {
+        Self {
+            status: status.into(),
+            compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
+            timeout: Some(DEFAULT_ACCEPT_TIMEOUT),
+            #[cfg(feature = "online")]
+            online_mode: None,
+        }
+    }
+
+    /// Handles the handshake, status and login of a freshly accepted client.
+    pub async fn accept(&self, stream: TcpStream) -> Result<Accepted> {
+        self.accept_conn(Conn::try_from(stream)?).await
+    }
+
+    /// Same as [`Server::accept`] but for any connection, e.g. one half of [`Conn::pair`].
+    pub async fn accept_conn<R, W>(&self, mut conn: Conn<R, W>) -> Result<Accepted<R, W>>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        conn.set_side(Side::Server);
+        conn.set_deadline(self.timeout.map(|timeout| Instant::now() + timeout));
+        if conn.is_legacy_ping().await? {
+            legacy::answer(&mut conn, &LegacyStatus::from_json(&self.status)?).await?;
+            return Ok(Accepted::Status);
+        }
+        let handshaking::Serverbound::Handshake(handshake) = conn.read_typed().await?;
+
+        match handshake.next_state {
+            HandshakeState::Status => {
+                conn.set_state(ProtocolState::Status);
+                self.status(&mut conn).await?;
+                Ok(Accepted::Status)
+            }
+            HandshakeState::Login => {
+                conn.set_state(ProtocolState::Login);
+                if handshake.protocol_version != PROTOCOL_VERSION {
+                    disconnect(&mut conn, "Outdated client! Please use 1.8.9").await?;
+                    return Err(Error::Invalid(format!(
+                        "client uses protocol version {} instead of {}",
+                        handshake.protocol_version, PROTOCOL_VERSION
+                    )));
+                }
+                Ok(Accepted::Login(Box::new(self.login(conn).await?)))
+            }
+        }
+    }
+
+    /// Answers status requests and pings until the client disconnects or has pinged.
+    async fn status<R, W>(&self, conn: &mut Conn<R, W>) -> Result<()>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        loop {
+            let packet = match conn.read_typed().await {
+                Ok(packet) => packet,
+                // Clients that only want the status close the connection without pinging
+                Err(e) if e.is_eof() => return Ok(()),
+                Err(e) => return Err(e),
+            };
+
+            match packet {
+                status::Serverbound::Request(_) => {
+                    conn.send_packet(status::clientbound::Response {
+                        response: self.status.clone(),
+                    })
+                    .await?;
+                }
+                status::Serverbound::Ping(ping) => {
+                    conn.send_packet(status::clientbound::Pong {
+                        payload: ping.payload,
+                    })
+                    .await?;
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    async fn login<R, W>(&self, mut conn: Conn<R, W>) -> Result<Player<R, W>>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let name = match conn.read_typed().await? {
+            login::Serverbound::LoginStart(p) => p.name,
+            login::Serverbound::EncryptionResponse(_) => {
+                disconnect(&mut conn, "Unexpected packet").await?;
+                return Err(Error::Invalid(
+                    "expected LoginStart but got EncryptionResponse".to_owned(),
+                ));
+            }
+        };
+
+        if name.is_empty() || name.len() > 16 {
+            disconnect(&mut conn, "Invalid username").await?;
+            return Err(Error::Invalid(format!("invalid username: {:?}", name)));
+        }
+
+        #[cfg(feature = "online")]
+        let (uuid, name, properties) = match &self.online_mode {
+            Some(online_mode) => {
+                let profile = online_mode.authenticate(&mut conn, &name).await?;
+                (profile.id, profile.name, profile.properties)
+            }
+            None => (offline_uuid(&name), name, Vec::new()),
+        };
+        #[cfg(not(feature = "online"))]
+        let uuid = offline_uuid(&name);
+
+        if self.compression_threshold >= 0 {
+            conn.send_packet(login::clientbound::SetCompression {
+                threshold: self.compression_threshold,
+            })
+            .await?;
+            conn.set_compression_threshhold(self.compression_threshold);
+        }
+
+        conn.send_packet(login::clientbound::LoginSuccess {
+            uuid: uuid.hyphenated().to_string(),
+            username: name.clone(),
+        })
+        .await?;
+        conn.set_state(ProtocolState::Play);
+        conn.set_deadline(None);
+
+        Ok(Player {
+            conn,
+            name,
+            uuid,
+            #[cfg(feature = "online")]
+            properties,
+        })
+    }
+}
+
+/// The rsa keypair a server encrypts the shared secret of its clients with.
+#[derive(Clone)]
+pub(crate) struct Keypair {
+    key: RsaPrivateKey,
+    /// The DER encoded public key that is sent to clients.
+    public_key: Vec<u8>,
+}
+
+/// Leaves out the private key, so servers can be logged.
+impl std::fmt::Debug for Keypair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Keypair")
+            .field("public_key", &self.public_key)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Keypair {
+    /// Generates a new 1024 bit rsa keypair, like vanilla servers do on startup.
+    pub(crate) fn generate() -> Result<Self> {
+        let key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024)?;
+        let public_key = key.to_public_key().to_public_key_der()?.into_vec();
+        Ok(Self { key, public_key })
+    }
+
+    pub(crate) fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    pub(crate) fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(self.key.decrypt(Pkcs1v15Encrypt, data)?)
+    }
+}
+
+#[cfg(feature = "online")]
+impl OnlineMode {
+    /// Generates a new 1024 bit rsa keypair, like vanilla servers do on startup.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            keypair: Keypair::generate()?,
+            session_url: DEFAULT_SESSION_URL.to_owned(),
+            http: reqwest::Client::new(),
+        })
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        self.keypair.public_key()
+    }
+
+    /// Does the encryption handshake with a client that sent `LoginStart` and checks with the session server
+    /// that they own the account. Encryption is enabled on `conn` afterwards.
+    async fn authenticate<R, W>(&self, conn: &mut Conn<R, W>, name: &str) -> Result<GameProfile>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let verify_token = rand::thread_rng().gen::<[u8; 4]>();
+        conn.send_packet(login::clientbound::EncryptionRequest {
+            // Servers since 1.7 send an empty server id
+            server_id: String::new(),
+            public_key: self.public_key().to_vec(),
+            verify_token: verify_token.to_vec(),
+        })
+        .await?;
+
+        let response = match conn.read_typed().await? {
+            login::Serverbound::EncryptionResponse(p) => p,
+            login::Serverbound::LoginStart(_) => {
+                disconnect(conn, "Unexpected packet").await?;
+                return Err(Error::Invalid(
+                    "expected EncryptionResponse but got LoginStart".to_owned(),
+                ));
+            }
+        };
+
+        let token = self.keypair.decrypt(&response.verify_token)?;
+        if token != verify_token {
+            disconnect(conn, "Invalid verify token").await?;
+            return Err(Error::Auth("verify token doesn't match".to_owned()));
+        }
+
+        let shared_secret = self.keypair.decrypt(&response.shared_secret)?;
+        conn.enable_encryption(&shared_secret)?;
+
+        let hash = session::server_hash("", &shared_secret, self.public_key());
+        match session::has_joined(&self.http, &self.session_url, name, &hash).await? {
+            Some(profile) => Ok(profile),
+            None => {
+                disconnect(conn, "Failed to verify username!").await?;
+                Err(Error::Auth(format!(
+                    "{} hasn't joined with the session server",
+                    name
+                )))
+            }
+        }
+    }
+}
+
+/// The uuid an offline mode server gives a player, which is a version 3 uuid of `OfflinePlayer:<name>`.
+pub fn offline_uuid(name: &str) -> Uuid {
+    let hash = Md5::digest(format!("OfflinePlayer:{}", name));
+    uuid::Builder::from_md5_bytes(hash.into()).into_uuid()
+}
+
+/// Sends a login disconnect with `reason` as plain text.
+async fn disconnect<R, W>(conn: &mut Conn<R, W>, reason: &str) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    conn.send_packet(login::clientbound::Disconnect {
+        reason: chat_text(reason),
+    })
+    .await?;
+    Ok(())
+}
+
+/// Turns `text` into a chat component.
+pub(crate) fn chat_text(text: &str) -> String {
+    serde_json::json!({ "text": text }).to_string()
+}
@@ -0,0 +1,124 @@
+//! Talking to the session server, which is how online mode servers check that players own their account.
+use super::{types::Uuid, Error, Result};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+/// The session server used by vanilla clients and servers.
+pub const DEFAULT_SESSION_URL: &str = "https://sessionserver.mojang.com";
+
+/// The profile the session server returns for a player that has joined.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct GameProfile {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(default)]
+    pub properties: Vec<ProfileProperty>,
+}
+
+/// A signed property of a profile, usually the skin textures.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ProfileProperty {
+    pub name: String,
+    pub value: String,
+    pub signature: Option<String>,
+}
+
+/// Computes the server id hash that both sides send to the session server.
+/// This is the sha1 of the three inputs as a signed big endian number in hex, without leading zeros.
+pub fn server_hash(server_id: &str, shared_secret: &[u8], public_key: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(server_id.as_bytes());
+    hasher.update(shared_secret);
+    hasher.update(public_key);
+    let mut hash = hasher.finalize();
+
+    let negative = (hash[0] & 0x80) == 0x80;
+    if negative {
+        // Two's complement
+        let mut carry = true;
+        for b in hash.iter_mut().rev() {
+            *b = !*b;
+            if carry {
+                carry = *b == 0xFF;
+                *b = b.wrapping_add(1);
+            }
+        }
+    }
+
+    let hex = hash.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    let hex = hex.trim_start_matches('0');
+    if negative {
+        format!("-{}", hex)
+    } else {
+        hex.to_owned()
+    }
+}
+
+/// Asks the session server at `session_url` whether `username` joined the server with `server_hash`.
+/// Returns `None` if they didn't.
+pub async fn has_joined(
+    http: &reqwest::Client,
+    session_url: &str,
+    username: &str,
+    server_hash: &str,
+) -> Result<Option<GameProfile>> {
+    let resp = http
+        .get(format!(
+            "{}/session/minecraft/hasJoined",
+            session_url.trim_end_matches('/')
+        ))
+        .query(&[("username", username), ("serverId", server_hash)])
+        .send()
+        .await?;
+
+    match resp.status() {
+        StatusCode::OK => Ok(Some(resp.json().await?)),
+        StatusCode::NO_CONTENT => Ok(None),
+        status => Err(Error::Auth(format!(
+            "session server responded with {}",
+            status
+        ))),
+    }
+}
+
+/// Tells the session server at `session_url` that the player is joining a server with `server_hash`.
+/// This is what clients do before answering an encryption request.
+pub async fn join(
+    http: &reqwest::Client,
+    session_url: &str,
+    access_token: &str,
+    uuid: &str,
+    server_hash: &str,
+) -> Result<()> {
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Join<'a> {
+        access_token: &'a str,
+        selected_profile: &'a str,
+        server_id: &'a str,
+    }
+
+    let resp = http
+        .post(format!(
+            "{}/session/minecraft/join",
+            session_url.trim_end_matches('/')
+        ))
+        .json(&Join {
+            access_token,
+            selected_profile: uuid,
+            server_id: server_hash,
+        })
+        .send()
+        .await?;
+
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(Error::Auth(format!(
+            "session server responded with {}: {}",
+            status,
+            resp.text().await.unwrap_or_default()
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,58 @@
+//! Traffic counters of a connection or codec, see [`Conn::enable_stats`](super::conn::Conn::enable_stats).
+//! ```ignore
+//! conn.enable_stats();
+//! // ...
+//! let stats = conn.stats().unwrap();
+//! println!("{} bytes in, ratio {:?}", stats.received.bytes, stats.received.compression_ratio());
+//! conn.reset_stats();
+//! ```
+//! Connections and codecs only count once stats are enabled, so they cost nothing otherwise.
+use super::types::VarInt;
+use std::collections::HashMap;
+
+/// The traffic in both directions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub sent: Traffic,
+    pub received: Traffic,
+}
+
+/// The traffic in one direction. Bytes are counted before encryption, which doesn't change their number.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Traffic {
+    pub packets: u64,
+    /// The bytes of the frames including their length prefix, which is what goes over the wire.
+    pub bytes: u64,
+    /// The bytes of the packet ids and data before compression.
+    pub uncompressed_bytes: u64,
+    /// How many of the packets were compressed.
+    pub compressed_packets: u64,
+    /// How many packets there were of each packet id, the ids of all states are counted together.
+    pub packets_by_id: HashMap<i32, u64>,
+}
+
+impl Traffic {
+    /// The bytes on the wire per uncompressed byte, which is below 1 if compression saved space.
+    /// Returns `None` if nothing was counted yet.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        match self.uncompressed_bytes {
+            0 => None,
+            uncompressed => Some(self.bytes as f64 / uncompressed as f64),
+        }
+    }
+
+    /// Counts a packet with `id` that took up `bytes` on the wire and `uncompressed_bytes` before compression.
+    pub(crate) fn record(
+        &mut self,
+        id: VarInt,
+        bytes: usize,
+        uncompressed_bytes: usize,
+        compressed: bool,
+    ) {
+        self.packets += 1;
+        self.bytes += bytes as u64;
+        self.uncompressed_bytes += uncompressed_bytes as u64;
+        self.compressed_packets += compressed as u64;
+        *self.packets_by_id.entry(id.0).or_default() += 1;
+    }
+}
@@ -0,0 +1,255 @@
+//! The status JSON of the server list ping, see [`StatusResponse`], and [`ping`] to ask a server for it.
+//! ```ignore
+//! let (status, latency) = status::ping("127.0.0.1:25565").await?;
+//! println!("{} in {:?}", status.description.to_plain_text(), latency);
+//! ```
+use super::{
+    conn::Conn,
+    packet::{ProtocolState, Side},
+    Error, Result,
+};
+use crate::p47::{enums::HandshakeState, handshaking, status, PROTOCOL_VERSION};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// The port used when the address doesn't have one.
+pub const DEFAULT_PORT: u16 = 25565;
+
+/// How long connecting and pinging may take in [`ping`].
+pub const DEFAULT_PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The start of the data URI of a favicon.
+const FAVICON_PREFIX: &str = "data:image/png;base64,";
+
+/// The first bytes of every PNG.
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// The status servers send in [`status::clientbound::Response`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatusResponse {
+    pub version: Version,
+    /// `None` hides the player counts in the server list.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub players: Option<Players>,
+    /// The motd.
+    #[serde(default)]
+    pub description: Chat,
+    /// A 64x64 PNG as a data URI, see [`StatusResponse::favicon_png`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub favicon: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Version {
+    pub name: String,
+    /// Clients with a different protocol version see the name in red.
+    pub protocol: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Players {
+    pub max: i32,
+    pub online: i32,
+    /// Shown when hovering over the player counts.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sample: Vec<PlayerSample>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerSample {
+    pub name: String,
+    /// The uuid with hyphens.
+    pub id: String,
+}
+
+/// A chat component, which is plain text, an object with formatting or a list of components.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Chat {
+    Text(String),
+    Component(ChatComponent),
+    List(Vec<Chat>),
+}
+
+/// A chat component with its formatting, the components in `extra` inherit it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatComponent {
+    #[serde(default)]
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bold: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub italic: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub underlined: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strikethrough: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub obfuscated: Option<bool>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra: Vec<Chat>,
+}
+
+impl Default for Chat {
+    fn default() -> Self {
+        Chat::Text(String::new())
+    }
+}
+
+impl From<&str> for Chat {
+    fn from(text: &str) -> Self {
+        Chat::Text(text.to_owned())
+    }
+}
+
+impl From<String> for Chat {
+    fn from(text: String) -> Self {
+        Chat::Text(text)
+    }
+}
+
+impl Chat {
+    /// The text without any formatting.
+    pub fn to_plain_text(&self) -> String {
+        let mut text = String::new();
+        self.push_plain_text(&mut text);
+        text
+    }
+
+    fn push_plain_text(&self, out: &mut String) {
+        match self {
+            Chat::Text(text) => out.push_str(text),
+            Chat::Component(component) => {
+                out.push_str(&component.text);
+                for chat in &component.extra {
+                    chat.push_plain_text(out);
+                }
+            }
+            Chat::List(parts) => {
+                for chat in parts {
+                    chat.push_plain_text(out);
+                }
+            }
+        }
+    }
+}
+
+impl StatusResponse {
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).map_err(|e| Error::Invalid(format!("invalid status: {}", e)))
+    }
+
+    /// The JSON for [`status::clientbound::Response`] or [`Server::new`](super::server::Server::new).
+    pub fn to_json(&self) -> String {
+        // Only strings and numbers, which always serialize
+        serde_json::to_string(self).expect("status serializes")
+    }
+
+    /// Decodes the favicon into the bytes of the PNG, `None` if the server has no favicon.
+    pub fn favicon_png(&self) -> Result<Option<Vec<u8>>> {
+        let Some(favicon) = &self.favicon else {
+            return Ok(None);
+        };
+        let Some(data) = favicon.strip_prefix(FAVICON_PREFIX) else {
+            return Err(Error::Invalid("favicon isn't a png data uri".to_owned()));
+        };
+        // Some servers wrap the base64 in lines
+        let data: String = data.split_whitespace().collect();
+        let png =
+            base64::decode(data).map_err(|e| Error::Invalid(format!("invalid favicon: {}", e)))?;
+        if !png.starts_with(PNG_SIGNATURE) {
+            return Err(Error::Invalid("favicon isn't a png".to_owned()));
+        }
+        Ok(Some(png))
+    }
+
+    /// Sets the favicon to `png`, which clients only show if it is 64x64 pixels.
+    pub fn set_favicon_png(&mut self, png: &[u8]) -> Result<()> {
+        if !png.starts_with(PNG_SIGNATURE) {
+            return Err(Error::Invalid("favicon isn't a png".to_owned()));
+        }
+        self.favicon = Some(format!("{}{}", FAVICON_PREFIX, base64::encode(png)));
+        Ok(())
+    }
+}
+
+/// Connects to `addr`, which is `host` or `host:port`, asks for its status and measures the latency
+/// of a ping, which doesn't include connecting. Gives up after [`DEFAULT_PING_TIMEOUT`].
+pub async fn ping(addr: &str) -> Result<(StatusResponse, Duration)> {
+    let (host, port) = split_addr(addr)?;
+    let mut conn = Conn::connect_timeout((host, port), DEFAULT_PING_TIMEOUT).await?;
+    conn.set_deadline(Some(Instant::now() + DEFAULT_PING_TIMEOUT));
+    ping_conn(&mut conn, host, port).await
+}
+
+/// Same as [`ping`] but over an existing connection in the handshaking state.
+/// `host` and `port` are sent in the handshake.
+/// Use [`Conn::set_deadline`] to bound how long it takes.
+pub async fn ping_conn<R, W>(
+    conn: &mut Conn<R, W>,
+    host: &str,
+    port: u16,
+) -> Result<(StatusResponse, Duration)>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    conn.set_side(Side::Client);
+    conn.send_packet(handshaking::serverbound::Handshake {
+        protocol_version: PROTOCOL_VERSION,
+        server_address: host.to_owned(),
+        server_port: port,
+        next_state: HandshakeState::Status,
+    })
+    .await?;
+    conn.set_state(ProtocolState::Status);
+
+    conn.send_packet(status::serverbound::Request {}).await?;
+    let status = match conn.read_typed().await? {
+        status::Clientbound::Response(p) => StatusResponse::from_json(&p.response)?,
+        _ => return Err(Error::Invalid("expected the status".to_owned())),
+    };
+
+    // Vanilla sends the time in milliseconds
+    let payload = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_millis() as i64);
+    let start = Instant::now();
+    conn.send_packet(status::serverbound::Ping { payload })
+        .await?;
+    match conn.read_typed().await? {
+        status::Clientbound::Pong(p) if p.payload == payload => {}
+        status::Clientbound::Pong(_) => {
+            return Err(Error::Invalid("pong doesn't match the ping".to_owned()))
+        }
+        _ => return Err(Error::Invalid("expected a pong".to_owned())),
+    }
+    Ok((status, start.elapsed()))
+}
+
+/// Splits `host:port` and uses [`DEFAULT_PORT`] if there is none.
+/// IPv6 addresses only have a port when they are in brackets like `[::1]:25565`, `::1` is just the host.
+pub fn split_addr(addr: &str) -> Result<(&str, u16)> {
+    let port = |port: &str| {
+        port.parse()
+            .map_err(|_| Error::Invalid(format!("invalid port: {:?}", port)))
+    };
+    if let Some(rest) = addr.strip_prefix('[') {
+        let (host, rest) = rest
+            .split_once(']')
+            .ok_or_else(|| Error::Invalid(format!("unclosed bracket in address: {:?}", addr)))?;
+        return match rest.strip_prefix(':') {
+            Some(p) => Ok((host, port(p)?)),
+            None if rest.is_empty() => Ok((host, DEFAULT_PORT)),
+            None => Err(Error::Invalid(format!("invalid address: {:?}", addr))),
+        };
+    }
+    match addr.split_once(':') {
+        // More than one colon is a bare IPv6 address
+        Some((host, p)) if !p.contains(':') => Ok((host, port(p)?)),
+        _ => Ok((addr, DEFAULT_PORT)),
+    }
+}
@@ -4,7 +4,10 @@ pub use crate::inv::{
     item::{Item, Itemstack},
     Slot,
 };
-use anyhow::{bail, Result};
+use super::{
+    codec::Limits,
+    Error, Limit, Result,
+};
 pub use nbt::Blob as Nbt;
 use std::{
     borrow::Cow,
@@ -15,6 +18,17 @@ use std::{
 };
 pub use uuid::Uuid;
 
+fn check_array_length(len: usize, max: usize) -> Result<usize> {
+    if len > max {
+        return Err(Error::TooLarge {
+            limit: Limit::Array,
+            len,
+            max,
+        });
+    }
+    Ok(len)
+}
+
 pub trait Encoder {
     fn write_to(&self, w: &mut impl io::Write) -> Result<()>;
 }
@@ -29,6 +43,12 @@ where
 }
 pub trait Decoder: Sized {
     fn read_from(r: &mut impl io::Read) -> Result<Self>;
+
+    /// Same as [`Decoder::read_from`] but array fields are bounded by `limits` instead of the default [`Limits`].
+    /// Types that contain arrays override this and pass `limits` on to their fields.
+    fn read_with(r: &mut impl io::Read, _limits: &Limits) -> Result<Self> {
+        Self::read_from(r)
+    }
 }
 
 impl Encoder for u8 {
@@ -159,10 +179,14 @@ where
     T: Decoder,
 {
     fn read_from(r: &mut impl io::Read) -> Result<Self> {
+        Self::read_with(r, &Limits::default())
+    }
+
+    fn read_with(r: &mut impl io::Read, limits: &Limits) -> Result<Self> {
         let present = bool::read_from(r)?;
 
         match present {
-            true => Ok(Some(T::read_from(r)?)),
+            true => Ok(Some(T::read_with(r, limits)?)),
             false => Ok(None),
         }
     }
@@ -300,13 +324,6 @@ pub struct Array<'a, T, U>(pub Cow<'a, [T]>, PhantomData<U>)
 where
     [T]: ToOwned<Owned = Vec<T>>;
 
-impl<'a, T, U> Array<'a, T, U>
-where
-    [T]: ToOwned<Owned = Vec<T>>,
-{
-    const MAX_LENGTH: usize = 1024 * 1024;
-}
-
 impl<'a, T, U> Encoder for Array<'a, T, U>
 where
     T: Encoder,
@@ -315,7 +332,13 @@ where
     [T]: ToOwned<Owned = Vec<T>>,
 {
     fn write_to(&self, w: &mut impl io::Write) -> Result<()> {
-        let len = U::try_from(self.0.len())?;
+        let len = U::try_from(self.0.len()).map_err(|e| {
+            Error::Invalid(format!(
+                "array length {} doesn't fit its prefix: {}",
+                self.0.len(),
+                e
+            ))
+        })?;
         len.write_to(w)?;
         for i in self.0.iter() {
             i.write_to(w)?;
@@ -332,14 +355,18 @@ where
     [T]: ToOwned<Owned = Vec<T>>,
 {
     fn read_from(r: &mut impl io::Read) -> Result<Self> {
-        let len: usize = U::read_from(r)?.try_into()?;
-        if len > Self::MAX_LENGTH {
-            bail!("array length too large! {} > 2^20", len)
-        }
+        Self::read_with(r, &Limits::default())
+    }
+
+    fn read_with(r: &mut impl io::Read, limits: &Limits) -> Result<Self> {
+        let len: usize = U::read_from(r)?
+            .try_into()
+            .map_err(|e| Error::Invalid(format!("invalid array length: {}", e)))?;
+        check_array_length(len, limits.max_array_length)?;
 
         let mut vec = Vec::<T>::new();
         for _ in 0..len {
-            vec.push(T::read_from(r)?);
+            vec.push(T::read_with(r, limits)?);
         }
         Ok(Self(Cow::Owned(vec), PhantomData))
     }
@@ -373,7 +400,8 @@ where
 }
 
 pub type VarIntPrefixedArray<'a, T> = Array<'a, T, VarInt>;
-pub type ShortPrefixedArray<'a, T> = Array<'a, T, u8>;
+pub type ShortPrefixedArray<'a, T> = Array<'a, T, i16>;
+pub type IntPrefixedArray<'a, T> = Array<'a, T, i32>;
 pub type ByteArray<'a, U> = Array<'a, u8, U>;
 pub type BitSet<'a, U> = Array<'a, u8, U>;
 
@@ -407,23 +435,74 @@ impl<'a> From<LengthInferredByteArray<'a>> for Vec<u8> {
 
 // ----------------------------------------------------------------------------
 
-pub type Angle = u8;
+/// An optional NBT compound which is encoded as a single `TAG_End` byte when absent.
+pub struct OptionalNbt<'a>(pub Option<Cow<'a, Nbt>>);
+
+impl<'a> Encoder for OptionalNbt<'a> {
+    fn write_to(&self, w: &mut impl io::Write) -> Result<()> {
+        raw::write_optional_nbt(w, self.0.as_deref())
+    }
+}
+
+impl<'a> Decoder for OptionalNbt<'a> {
+    fn read_from(r: &mut impl io::Read) -> Result<Self> {
+        Ok(OptionalNbt(raw::read_optional_nbt(r)?.map(Cow::Owned)))
+    }
+}
+
+impl<'a> From<Option<&'a Nbt>> for OptionalNbt<'a> {
+    fn from(nbt: Option<&'a Nbt>) -> Self {
+        OptionalNbt(nbt.map(Cow::Borrowed))
+    }
+}
+
+impl<'a> From<OptionalNbt<'a>> for Option<Nbt> {
+    fn from(nbt: OptionalNbt<'a>) -> Self {
+        nbt.0.map(Cow::into_owned)
+    }
+}
+
+/// A rotation in steps of 1/256 of a full turn, packets made with `packets!` expose it in degrees.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Angle(pub u8);
+
+impl Encoder for Angle {
+    fn write_to(&self, w: &mut impl io::Write) -> Result<()> {
+        self.0.write_to(w)
+    }
+}
+
+impl Decoder for Angle {
+    fn read_from(r: &mut impl io::Read) -> Result<Self> {
+        Ok(Self(u8::read_from(r)?))
+    }
+}
+
+impl From<f32> for Angle {
+    /// Wraps `degrees` around, so 270 and -90 are the same angle.
+    fn from(degrees: f32) -> Self {
+        Self((degrees / 360.0 * 256.0).round().rem_euclid(256.0) as u8)
+    }
+}
+
+impl From<Angle> for f32 {
+    /// The angle in degrees from 0 up to 360.
+    fn from(angle: Angle) -> Self {
+        angle.0 as f32 * 360.0 / 256.0
+    }
+}
 
 impl<T: Item, U: Enchant> Encoder for Slot<T, U> {
     fn write_to(&self, w: &mut impl io::Write) -> Result<()> {
         match self {
-            Self::Empty => false.write_to(w),
+            Self::Empty => (-1_i16).write_to(w),
             Self::Filled(i) => {
-                true.write_to(w)?;
-                VarInt(i.item.id().into()).write_to(w)?;
-
-                let count: i8 = i.count.into();
-                count.write_to(w)?;
+                (i.item.id() as i16).write_to(w)?;
+                i.count.write_to(w)?;
+                i.damage.write_to(w)?;
 
                 // TODO: Handle NBT properly
-                0_u8.write_to(w)?;
-
-                Ok(())
+                raw::write_optional_nbt(w, None)
             }
         }
     }
@@ -431,23 +510,19 @@ impl<T: Item, U: Enchant> Encoder for Slot<T, U> {
 
 impl<T: Item, U: Enchant> Decoder for Slot<T, U> {
     fn read_from(r: &mut impl io::Read) -> Result<Self> {
-        let present: bool = bool::read_from(r)?;
-        Ok(match present {
-            false => Self::Empty,
-            true => {
-                Self::Filled(Itemstack {
-                    item: {
-                        let id = VarInt::read_from(r)?.0 as u16;
-                        T::from_id(id)?
-                    },
-                    count: i8::read_from(r)?,
-                    meta: {
-                        nbt::from_reader(r)?;
-                        // TODO: Handle NBT properly
-                        None
-                    },
-                })
-            }
+        let id = i16::read_from(r)?;
+        Ok(match id {
+            -1 => Self::Empty,
+            id => Self::Filled(Itemstack {
+                item: T::from_id(id as u16).map_err(|e| Error::Invalid(e.to_string()))?,
+                count: i8::read_from(r)?,
+                damage: i16::read_from(r)?,
+                meta: {
+                    raw::read_optional_nbt(r)?;
+                    // TODO: Handle NBT properly
+                    None
+                },
+            }),
         })
     }
 }
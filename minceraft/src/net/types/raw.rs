@@ -1,6 +1,7 @@
-use anyhow::{bail, Result};
+use super::check_array_length;
+use crate::net::{Error, Limit, Result};
 use byteorder::{ReadBytesExt, WriteBytesExt, BE};
-use std::io;
+use std::io::{self, Read};
 use uuid::Uuid;
 
 #[inline]
@@ -16,7 +17,7 @@ pub fn read_bool(t: &mut impl io::Read) -> Result<bool> {
     } else if i == 1 {
         return Ok(true);
     }
-    Err(anyhow::Error::msg("not a bool"))
+    Err(Error::Invalid(format!("not a bool: {}", i)))
 }
 
 #[inline]
@@ -105,20 +106,45 @@ pub fn write_string(t: &mut impl io::Write, v: &String) -> Result<()> {
     t.write_all(v.as_bytes()).map_err(From::from)
 }
 
+/// Reads a VarInt length prefix, which can't be negative.
+#[inline]
+pub fn read_length(t: &mut impl io::Read) -> Result<usize> {
+    let len = read_var_int(t)?;
+    usize::try_from(len).map_err(|_| Error::Invalid(format!("length is negative: {}", len)))
+}
+
+/// Reads `len` bytes without trusting `len` for the allocation, so a short packet can't claim gigabytes.
+#[inline]
+pub fn read_bytes(t: &mut impl io::Read, len: usize) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    t.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() != len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(buf)
+}
+
+/// Reads a VarInt length prefix of an array and checks that it has at most `max` elements.
+#[inline]
+pub fn read_array_length(t: &mut impl io::Read, max: usize) -> Result<usize> {
+    check_array_length(read_length(t)?, max)
+}
+
 #[inline]
 pub fn read_string(t: &mut impl io::Read) -> Result<String> {
-    let len = read_var_int(t)? as usize;
-    let mut buf = vec![0; len];
-    t.read_exact(&mut buf)?;
-    Ok(String::from_utf8(buf)?)
+    let len = read_length(t)?;
+    let buf = read_bytes(t, len)?;
+    String::from_utf8(buf).map_err(|e| Error::Invalid(e.to_string()))
 }
 
 #[inline]
 pub fn write_chat(t: &mut impl io::Write, v: &String) -> Result<()> {
     if v.len() > 262144 {
-        return Err(anyhow::Error::msg(
-            "chat message exceeds maximum length of 26144",
-        ));
+        return Err(Error::TooLarge {
+            limit: Limit::Chat,
+            len: v.len(),
+            max: 262144,
+        });
     }
     write_string(t, v)
 }
@@ -127,9 +153,11 @@ pub fn write_chat(t: &mut impl io::Write, v: &String) -> Result<()> {
 pub fn read_chat(t: &mut impl io::Read) -> Result<String> {
     let chat = read_string(t)?;
     if chat.len() > 262144 {
-        return Err(anyhow::Error::msg(
-            "chat message exceeds maximum length of 26144",
-        ));
+        return Err(Error::TooLarge {
+            limit: Limit::Chat,
+            len: chat.len(),
+            max: 262144,
+        });
     }
     Ok(chat)
 }
@@ -137,9 +165,11 @@ pub fn read_chat(t: &mut impl io::Read) -> Result<String> {
 #[inline]
 pub fn write_identifier(t: &mut impl io::Write, v: &String) -> Result<()> {
     if v.len() > 32767 {
-        return Err(anyhow::Error::msg(
-            "identifier exceeds maximum length of 32767",
-        ));
+        return Err(Error::TooLarge {
+            limit: Limit::Identifier,
+            len: v.len(),
+            max: 32767,
+        });
     }
     write_string(t, v)
 }
@@ -148,9 +178,11 @@ pub fn write_identifier(t: &mut impl io::Write, v: &String) -> Result<()> {
 pub fn read_identifier(t: &mut impl io::Read) -> Result<String> {
     let id = read_string(t)?;
     if id.len() > 32767 {
-        return Err(anyhow::Error::msg(
-            "identifier exceeds maximum length of 32767",
-        ));
+        return Err(Error::TooLarge {
+            limit: Limit::Identifier,
+            len: id.len(),
+            max: 32767,
+        });
     }
     Ok(id)
 }
@@ -174,11 +206,9 @@ pub fn write_byte_array(t: &mut impl io::Write, v: &Vec<u8>) -> Result<()> {
 }
 
 #[inline]
-pub fn read_byte_array(t: &mut impl io::Read) -> Result<Vec<u8>> {
-    let len = read_var_int(t)? as usize;
-    let mut buf = vec![0; len];
-    t.read_exact(&mut buf[..])?;
-    Ok(buf)
+pub fn read_byte_array(t: &mut impl io::Read, max: usize) -> Result<Vec<u8>> {
+    let len = read_array_length(t, max)?;
+    read_bytes(t, len)
 }
 
 #[inline]
@@ -213,7 +243,7 @@ pub fn read_var_int(t: &mut impl io::Read) -> Result<i32> {
         num_read += 1;
 
         if num_read > 5 {
-            bail!("Varint is too large");
+            return Err(Error::VarIntTooLong);
         }
         if read & 0b1000_0000 == 0 {
             break;
@@ -256,7 +286,7 @@ pub fn read_var_long(t: &mut impl io::Read) -> Result<i64> {
         num_read += 1;
 
         if num_read > 10 {
-            bail!("VarLong is too large.");
+            return Err(Error::VarIntTooLong);
         }
         if read & 0b1000_0000 == 0 {
             break;
@@ -275,9 +305,9 @@ pub fn write_bitset(t: &mut impl io::Write, v: &Vec<i64>) -> Result<()> {
 }
 
 #[inline]
-pub fn read_bitset(t: &mut impl io::Read) -> Result<Vec<i64>> {
+pub fn read_bitset(t: &mut impl io::Read, max: usize) -> Result<Vec<i64>> {
     let mut data: Vec<i64> = Vec::new();
-    let len = read_var_int(t)? as usize;
+    let len = read_array_length(t, max)?;
     for i in 0..len {
         data.insert(i, read_long(t)?);
     }
@@ -321,3 +351,21 @@ pub fn write_nbt(t: &mut impl io::Write, v: &nbt::Blob) -> Result<()> {
 pub fn read_nbt(t: &mut impl io::Read) -> Result<nbt::Blob> {
     nbt::Blob::from_reader(t).map_err(From::from)
 }
+
+#[inline]
+pub fn write_optional_nbt(t: &mut impl io::Write, v: Option<&nbt::Blob>) -> Result<()> {
+    match v {
+        Some(v) => write_nbt(t, v),
+        None => write_unsigned_byte(t, 0),
+    }
+}
+
+#[inline]
+pub fn read_optional_nbt(t: &mut impl io::Read) -> Result<Option<nbt::Blob>> {
+    let tag = read_unsigned_byte(t)?;
+    if tag == 0 {
+        return Ok(None);
+    }
+    // Put the tag type back in front of the rest of the compound
+    read_nbt(&mut io::Read::chain(&[tag][..], t)).map(Some)
+}
@@ -47,6 +47,7 @@ def_enum! {
         0 = Survival,
         1 = Creative,
         2 = Adventure,
+        3 = Spectator,
     }
 }
 
@@ -99,3 +100,147 @@ def_enum! {
         23 = DisableReducedDebugInfo,
     }
 }
+
+def_enum! {
+    DiggingStatus(i8) {
+        0 = StartedDigging,
+        1 = CancelledDigging,
+        2 = FinishedDigging,
+        3 = DropItemStack,
+        4 = DropItem,
+        5 = ShootArrowOrFinishEating,
+    }
+}
+
+def_enum! {
+    UseEntityKind(VarInt) {
+        0 = Interact,
+        1 = Attack,
+        2 = InteractAt {
+            target_x f32;
+            target_y f32;
+            target_z f32;
+        },
+    }
+}
+
+def_enum! {
+    ScoreboardObjectiveMode(i8) {
+        0 = Create {
+            value String;
+            ty String;
+        },
+        1 = Remove,
+        2 = Update {
+            value String;
+            ty String;
+        },
+    }
+}
+
+def_enum! {
+    UpdateScoreAction(i8) {
+        0 = CreateOrUpdate {
+            objective_name String;
+            value VarInt;
+        },
+        1 = Remove {
+            objective_name String;
+        },
+    }
+}
+
+def_enum! {
+    TeamMode(i8) {
+        0 = Create {
+            display_name String;
+            prefix String;
+            suffix String;
+            friendly_fire i8;
+            name_tag_visibility String;
+            color i8;
+            players VarIntPrefixedArray<String>;
+        },
+        1 = Remove,
+        2 = UpdateInfo {
+            display_name String;
+            prefix String;
+            suffix String;
+            friendly_fire i8;
+            name_tag_visibility String;
+            color i8;
+        },
+        3 = AddPlayers {
+            players VarIntPrefixedArray<String>;
+        },
+        4 = RemovePlayers {
+            players VarIntPrefixedArray<String>;
+        },
+    }
+}
+
+def_enum! {
+    CombatEventKind(VarInt) {
+        0 = EnterCombat,
+        1 = EndCombat {
+            duration VarInt;
+            eid i32;
+        },
+        2 = EntityDead {
+            player_id VarInt;
+            eid i32;
+            message String;
+        },
+    }
+}
+
+def_enum! {
+    WorldBorderAction(VarInt) {
+        0 = SetSize {
+            diameter f64;
+        },
+        1 = LerpSize {
+            old_diameter f64;
+            new_diameter f64;
+            speed VarLong;
+        },
+        2 = SetCenter {
+            x f64;
+            z f64;
+        },
+        3 = Initialize {
+            x f64;
+            z f64;
+            old_diameter f64;
+            new_diameter f64;
+            speed VarLong;
+            portal_teleport_boundary VarInt;
+            warning_time VarInt;
+            warning_blocks VarInt;
+        },
+        4 = SetWarningTime {
+            warning_time VarInt;
+        },
+        5 = SetWarningBlocks {
+            warning_blocks VarInt;
+        },
+    }
+}
+
+def_enum! {
+    TitleAction(VarInt) {
+        0 = SetTitle {
+            text String;
+        },
+        1 = SetSubtitle {
+            text String;
+        },
+        2 = SetTimes {
+            fade_in i32;
+            stay i32;
+            fade_out i32;
+        },
+        3 = Hide,
+        4 = Reset,
+    }
+}
@@ -1,7 +1,10 @@
 use anyhow::bail;
 use std::collections::BTreeMap;
 
-use crate::net::types::{Decoder, Encoder, Position};
+use crate::net::{
+    types::{Decoder, Encoder, Position},
+    Error,
+};
 
 use super::inv::Slot;
 
@@ -101,7 +104,7 @@ impl EntityMetaData {
     }
 
     pub fn set<T: Into<EntityMetaDataEntry>>(&mut self, i: u8, value: T) -> anyhow::Result<()> {
-        if i | 0xE0 > 0x00 {
+        if i & 0xE0 != 0x00 {
             bail!("the top 3 bits of the index should be 0")
         }
         let value: EntityMetaDataEntry = value.into();
@@ -143,7 +146,7 @@ impl Default for EntityMetaData {
 }
 
 impl Encoder for EntityMetaData {
-    fn write_to(&self, w: &mut impl std::io::Write) -> anyhow::Result<()> {
+    fn write_to(&self, w: &mut impl std::io::Write) -> crate::net::Result<()> {
         for (i, v) in self.iter() {
             ((v.ty() << 5 | i/*& 0x1F*/) & 0xFF/* does & 0xFF even do anything? */).write_to(w)?;
             match v {
@@ -167,12 +170,12 @@ impl Encoder for EntityMetaData {
                 }
             }?;
         }
-        0x7F.write_to(w)
+        0x7F_u8.write_to(w)
     }
 }
 
 impl Decoder for EntityMetaData {
-    fn read_from(r: &mut impl std::io::Read) -> anyhow::Result<Self> {
+    fn read_from(r: &mut impl std::io::Read) -> crate::net::Result<Self> {
         let mut data = Self::new();
         loop {
             let byte = u8::read_from(r)?;
@@ -203,10 +206,14 @@ impl Decoder for EntityMetaData {
                     data.set(i, [pitch, yaw, roll])
                 }
                 _ => {
-                    bail!("Invalid type id!")
+                    return Err(Error::UnknownDiscriminant {
+                        enum_name: "EntityMetaDataEntry",
+                        value: format!("{:?}", ty),
+                    })
                 }
-            }?
+            }
+            .map_err(|e| Error::Invalid(e.to_string()))?
         }
-        todo!()
+        Ok(data)
     }
 }
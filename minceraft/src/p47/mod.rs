@@ -2,3 +2,8 @@ pub mod enums;
 pub mod inv;
 pub mod metadata;
 pub mod packets;
+
+pub use packets::*;
+
+/// The protocol version of minecraft 1.8.9.
+pub const PROTOCOL_VERSION: i32 = 47;
@@ -1 +1,3 @@
 pub mod serverbound;
+
+pub use serverbound::Serverbound;
@@ -1,5 +1,5 @@
 use crate::p47::enums::HandshakeState;
-use crate::packets;
+use crate::{packet_enum, packets};
 
 packets!(
     Handshake(0x00) {
@@ -9,3 +9,9 @@ packets!(
         next_state HandshakeState;
     },
 );
+
+packet_enum! {
+    Serverbound(Handshaking) {
+        Handshake,
+    }
+}
@@ -1,4 +1,4 @@
-use crate::packets;
+use crate::{packet_enum, packets};
 
 packets! {
     Disconnect(0x00) {
@@ -18,3 +18,12 @@ packets! {
         threshold VarInt;
     }
 }
+
+packet_enum! {
+    Clientbound(Login) {
+        Disconnect,
+        EncryptionRequest,
+        LoginSuccess,
+        SetCompression,
+    }
+}
@@ -1,4 +1,4 @@
-use crate::packets;
+use crate::{packet_enum, packets};
 
 packets! {
     LoginStart(0x00) {
@@ -9,3 +9,10 @@ packets! {
         verify_token VarIntPrefixedArray<u8>;
     },
 }
+
+packet_enum! {
+    Serverbound(Login) {
+        LoginStart,
+        EncryptionResponse,
+    }
+}
@@ -1,7 +1,9 @@
+use crate::net::codec::Limits;
 use crate::p47::enums::*;
 use crate::p47::inv::Slot;
 use crate::p47::metadata::EntityMetaData;
-use crate::packets;
+use crate::{def_enum, packet_enum, packets};
+use std::num::NonZeroU8;
 
 packets! {
     KeepAlive(0x00) {
@@ -38,7 +40,7 @@ packets! {
         food_saturation f32; // 0.0-5.0 in integer increments
     }
     Respawn(0x07) {
-        dimension Dimension;
+        dimension i32; // unlike JoinGame this is an int: -1 = nether, 0 = overworld, 1 = end
         difficulty Difficulty;
         gamemode Gamemode;
         level_type String;
@@ -114,14 +116,14 @@ packets! {
         z i32;
         count i16;
     }
-    EntityVelocity(0x11) {
+    EntityVelocity(0x12) {
         eid VarInt;
         velocity_x i16;
         velocity_y i16;
         velocity_z i16;
     }
     DestroyEntities(0x13) {
-        eids VarIntPrefixedArray<u8>
+        eids VarIntPrefixedArray<VarInt>
     }
     Entity(0x14) {
         eid VarInt;
@@ -170,7 +172,7 @@ packets! {
         vehicle_eid i32; // set to -1 to detach
         leash bool; // if true leashes the entity to the vehicle
     }
-    UpdataEntityMetaData(0x1C) {
+    UpdateEntityMetaData(0x1C) {
         eid VarInt;
         metadata EntityMetaData;
     }
@@ -190,10 +192,267 @@ packets! {
         level VarInt;
         total_experience VarInt;
     }
-    //TODO: finish entity properties packet, this includes making a proper attribute type which can be generated with https://github.com/PrismarineJS/minecraft-data/blob/master/data/pc/1.8/attributes.json
-    EntityProperties(20) {
+    EntityProperties(0x20) {
+        eid VarInt;
+        properties IntPrefixedArray<EntityProperty>;
+    }
+    ChunkData(0x21) {
+        chunk_x i32;
+        chunk_z i32;
+        ground_up_continuous bool;
+        primary_bit_mask u16;
+        data VarIntPrefixedArray<u8>;
+    }
+    MultiBlockChange(0x22) {
+        chunk_x i32;
+        chunk_z i32;
+        records VarIntPrefixedArray<BlockChangeRecord>;
+    }
+    BlockChange(0x23) {
+        location Position;
+        block_id VarInt; // id << 4 | meta
+    }
+    BlockAction(0x24) {
+        location Position;
+        byte_1 u8;
+        byte_2 u8;
+        block_type VarInt;
+    }
+    BlockBreakAnimation(0x25) {
+        eid VarInt;
+        location Position;
+        destroy_stage i8; // 0-9, anything else removes it
+    }
+    MapChunkBulk(0x26) {
+        sky_light_sent bool;
+        chunk_meta VarIntPrefixedArray<ChunkMeta>;
+        data LengthInferredByteArray;
+    }
+    Explosion(0x27) {
+        x f32;
+        y f32;
+        z f32;
+        radius f32;
+        records IntPrefixedArray<ExplosionRecord>;
+        player_motion_x f32;
+        player_motion_y f32;
+        player_motion_z f32;
+    }
+    Effect(0x28) {
+        effect_id i32;
+        location Position;
+        data i32;
+        disable_relative_volume bool;
+    }
+    SoundEffect(0x29) {
+        sound_name String;
+        x i32; // multiplied by 8
+        y i32; // multiplied by 8
+        z i32; // multiplied by 8
+        volume f32;
+        pitch u8; // 63 is 100%
+    }
+    Particle(0x2A) {
+        particle_id i32;
+        long_distance bool;
+        x f32;
+        y f32;
+        z f32;
+        offset_x f32;
+        offset_y f32;
+        offset_z f32;
+        particle_data f32;
+        count i32;
+        data ParticleData;
+    }
+    ChangeGameState(0x2B) {
+        reason u8;
+        value f32;
+    }
+    SpawnGlobalEntity(0x2C) {
         eid VarInt;
-        //...
+        ty i8; // always 1 (thunderbolt)
+        x i32;
+        y i32;
+        z i32;
+    }
+    CloseWindow(0x2E) {
+        window_id u8;
+    }
+    SetSlot(0x2F) {
+        window_id i8;
+        slot i16;
+        slot_data Slot;
+    }
+    WindowItems(0x30) {
+        window_id u8;
+        slot_data ShortPrefixedArray<Slot>;
+    }
+    WindowProperty(0x31) {
+        window_id u8;
+        property i16;
+        value i16;
+    }
+    ConfirmTransaction(0x32) {
+        window_id i8;
+        action_number i16;
+        accepted bool;
+    }
+    UpdateSign(0x33) {
+        location Position;
+        line_1 String;
+        line_2 String;
+        line_3 String;
+        line_4 String;
+    }
+    Map(0x34) {
+        item_damage VarInt;
+        scale i8;
+        icons VarIntPrefixedArray<MapIcon>;
+        update MapUpdate;
+    }
+    UpdateBlockEntity(0x35) {
+        location Position;
+        action u8;
+        nbt OptionalNbt;
+    }
+    OpenSignEditor(0x36) {
+        location Position;
+    }
+    Statistics(0x37) {
+        statistics VarIntPrefixedArray<Statistic>;
+    }
+    PlayerListItem(0x38) {
+        action PlayerListAction;
+    }
+    PlayerAbilities(0x39) {
+        flags i8; // Bit field
+        flying_speed f32;
+        field_of_view_modifier f32;
+    }
+    TabComplete(0x3A) {
+        matches VarIntPrefixedArray<String>;
+    }
+    ScoreboardObjective(0x3B) {
+        objective_name String;
+        mode ScoreboardObjectiveMode;
+    }
+    UpdateScore(0x3C) {
+        score_name String;
+        action UpdateScoreAction;
+    }
+    DisplayScoreboard(0x3D) {
+        position i8; // 0 = list, 1 = sidebar, 2 = below name
+        score_name String;
+    }
+    Teams(0x3E) {
+        team_name String;
+        mode TeamMode;
+    }
+    PluginMessage(0x3F) {
+        channel String;
+        data LengthInferredByteArray;
+    }
+    Disconnect(0x40) {
+        reason String; // to be replaced with a Chat type
+    }
+    ServerDifficulty(0x41) {
+        difficulty Difficulty;
+    }
+    CombatEvent(0x42) {
+        event CombatEventKind;
+    }
+    Camera(0x43) {
+        camera_id VarInt;
+    }
+    WorldBorder(0x44) {
+        action WorldBorderAction;
+    }
+    Title(0x45) {
+        action TitleAction;
+    }
+    SetCompression(0x46) {
+        threshold VarInt;
+    }
+    PlayerListHeaderAndFooter(0x47) {
+        header String; // to be replaced with a Chat type
+        footer String; // to be replaced with a Chat type
+    }
+    ResourcePackSend(0x48) {
+        url String;
+        hash String;
+    }
+    UpdateEntityNbt(0x49) {
+        eid VarInt;
+        tag Nbt;
+    }
+}
+
+/// Open Window can't be defined with `packets!` because the entity id is only sent for horse windows.
+#[derive(Debug, Clone)]
+pub struct OpenWindow {
+    pub window_id: u8,
+    pub window_type: String,
+    pub window_title: String, // to be replaced with a Chat type
+    pub slots: u8,
+    pub eid: Option<i32>, // only present if window_type is "EntityHorse"
+}
+
+impl Packet for OpenWindow {
+    const ID: VarInt = VarInt(0x2D);
+}
+
+impl Encoder for OpenWindow {
+    fn write_to(&self, w: &mut impl std::io::Write) -> crate::net::Result<()> {
+        self.window_id.write_to(w)?;
+        self.window_type.write_to(w)?;
+        self.window_title.write_to(w)?;
+        self.slots.write_to(w)?;
+        if self.window_type == "EntityHorse" {
+            self.eid.unwrap_or_default().write_to(w)?;
+        }
+        Ok(())
+    }
+}
+
+impl Decoder for OpenWindow {
+    fn read_from(r: &mut impl std::io::Read) -> crate::net::Result<Self> {
+        let window_id = u8::read_from(r)?;
+        let window_type = String::read_from(r)?;
+        let window_title = String::read_from(r)?;
+        let slots = u8::read_from(r)?;
+        let eid = if window_type == "EntityHorse" {
+            Some(i32::read_from(r)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            window_id,
+            window_type,
+            window_title,
+            slots,
+            eid,
+        })
+    }
+}
+
+def_enum! {
+    PlayerListAction(VarInt) {
+        0 = AddPlayer {
+            players VarIntPrefixedArray<PlayerListAddPlayer>;
+        },
+        1 = UpdateGamemode {
+            players VarIntPrefixedArray<PlayerListUpdateGamemode>;
+        },
+        2 = UpdateLatency {
+            players VarIntPrefixedArray<PlayerListUpdateLatency>;
+        },
+        3 = UpdateDisplayName {
+            players VarIntPrefixedArray<PlayerListUpdateDisplayName>;
+        },
+        4 = RemovePlayer {
+            players VarIntPrefixedArray<Uuid>;
+        },
     }
 }
 
@@ -204,7 +463,7 @@ pub struct SpawnObjectInner {
 }
 
 impl Encoder for SpawnObjectInner {
-    fn write_to(&self, w: &mut impl std::io::Write) -> anyhow::Result<()> {
+    fn write_to(&self, w: &mut impl std::io::Write) -> crate::net::Result<()> {
         self.data.write_to(w)?;
         if self.data != 0 {
             self.velocity.as_ref().unwrap().write_to(w)?;
@@ -214,7 +473,7 @@ impl Encoder for SpawnObjectInner {
 }
 
 impl Decoder for SpawnObjectInner {
-    fn read_from(r: &mut impl std::io::Read) -> anyhow::Result<Self> {
+    fn read_from(r: &mut impl std::io::Read) -> crate::net::Result<Self> {
         let data = i32::read_from(r)?;
         let velocity = if data != 0 {
             Some(SpawnObjectVelocity {
@@ -238,7 +497,7 @@ pub struct SpawnObjectVelocity {
 }
 
 impl Encoder for SpawnObjectVelocity {
-    fn write_to(&self, w: &mut impl std::io::Write) -> anyhow::Result<()> {
+    fn write_to(&self, w: &mut impl std::io::Write) -> crate::net::Result<()> {
         self.x.write_to(w)?;
         self.y.write_to(w)?;
         self.z.write_to(w)
@@ -246,7 +505,7 @@ impl Encoder for SpawnObjectVelocity {
 }
 
 impl Decoder for SpawnObjectVelocity {
-    fn read_from(r: &mut impl std::io::Read) -> anyhow::Result<Self> {
+    fn read_from(r: &mut impl std::io::Read) -> crate::net::Result<Self> {
         Ok(Self {
             x: i16::read_from(r)?,
             y: i16::read_from(r)?,
@@ -254,3 +513,507 @@ impl Decoder for SpawnObjectVelocity {
         })
     }
 }
+
+#[derive(Debug, Clone)]
+pub struct EntityProperty {
+    pub key: String,
+    pub value: f64,
+    pub modifiers: Vec<AttributeModifier>,
+}
+
+impl Encoder for EntityProperty {
+    fn write_to(&self, w: &mut impl std::io::Write) -> crate::net::Result<()> {
+        self.key.write_to(w)?;
+        self.value.write_to(w)?;
+        VarIntPrefixedArray::from(self.modifiers.as_slice()).write_to(w)
+    }
+}
+
+impl Decoder for EntityProperty {
+    fn read_from(r: &mut impl std::io::Read) -> crate::net::Result<Self> {
+        Self::read_with(r, &Limits::default())
+    }
+
+    fn read_with(r: &mut impl std::io::Read, limits: &Limits) -> crate::net::Result<Self> {
+        Ok(Self {
+            key: String::read_from(r)?,
+            value: f64::read_from(r)?,
+            modifiers: VarIntPrefixedArray::read_with(r, limits)?.into(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AttributeModifier {
+    pub uuid: Uuid,
+    pub amount: f64,
+    pub operation: i8,
+}
+
+impl Encoder for AttributeModifier {
+    fn write_to(&self, w: &mut impl std::io::Write) -> crate::net::Result<()> {
+        self.uuid.write_to(w)?;
+        self.amount.write_to(w)?;
+        self.operation.write_to(w)
+    }
+}
+
+impl Decoder for AttributeModifier {
+    fn read_from(r: &mut impl std::io::Read) -> crate::net::Result<Self> {
+        Ok(Self {
+            uuid: Uuid::read_from(r)?,
+            amount: f64::read_from(r)?,
+            operation: i8::read_from(r)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockChangeRecord {
+    pub horizontal_position: u8, // x << 4 | z, relative to the chunk
+    pub y: u8,
+    pub block_id: i32,
+}
+
+impl Encoder for BlockChangeRecord {
+    fn write_to(&self, w: &mut impl std::io::Write) -> crate::net::Result<()> {
+        self.horizontal_position.write_to(w)?;
+        self.y.write_to(w)?;
+        VarInt(self.block_id).write_to(w)
+    }
+}
+
+impl Decoder for BlockChangeRecord {
+    fn read_from(r: &mut impl std::io::Read) -> crate::net::Result<Self> {
+        Ok(Self {
+            horizontal_position: u8::read_from(r)?,
+            y: u8::read_from(r)?,
+            block_id: VarInt::read_from(r)?.0,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChunkMeta {
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+    pub primary_bit_mask: u16,
+}
+
+impl Encoder for ChunkMeta {
+    fn write_to(&self, w: &mut impl std::io::Write) -> crate::net::Result<()> {
+        self.chunk_x.write_to(w)?;
+        self.chunk_z.write_to(w)?;
+        self.primary_bit_mask.write_to(w)
+    }
+}
+
+impl Decoder for ChunkMeta {
+    fn read_from(r: &mut impl std::io::Read) -> crate::net::Result<Self> {
+        Ok(Self {
+            chunk_x: i32::read_from(r)?,
+            chunk_z: i32::read_from(r)?,
+            primary_bit_mask: u16::read_from(r)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExplosionRecord {
+    pub x: i8,
+    pub y: i8,
+    pub z: i8,
+}
+
+impl Encoder for ExplosionRecord {
+    fn write_to(&self, w: &mut impl std::io::Write) -> crate::net::Result<()> {
+        self.x.write_to(w)?;
+        self.y.write_to(w)?;
+        self.z.write_to(w)
+    }
+}
+
+impl Decoder for ExplosionRecord {
+    fn read_from(r: &mut impl std::io::Read) -> crate::net::Result<Self> {
+        Ok(Self {
+            x: i8::read_from(r)?,
+            y: i8::read_from(r)?,
+            z: i8::read_from(r)?,
+        })
+    }
+}
+
+/// The particle data array, its length depends on the particle id
+/// (2 for iconcrack, 1 for blockcrack and blockdust, 0 otherwise) so it is read until the end of the packet.
+#[derive(Debug, Clone)]
+pub struct ParticleData(pub Vec<i32>);
+
+impl Encoder for ParticleData {
+    fn write_to(&self, w: &mut impl std::io::Write) -> crate::net::Result<()> {
+        for i in self.0.iter() {
+            VarInt(*i).write_to(w)?;
+        }
+        Ok(())
+    }
+}
+
+impl Decoder for ParticleData {
+    fn read_from(r: &mut impl std::io::Read) -> crate::net::Result<Self> {
+        let mut rest = Vec::new();
+        r.read_to_end(&mut rest)?;
+        let mut rest = rest.as_slice();
+        let mut data = Vec::new();
+        while !rest.is_empty() {
+            data.push(VarInt::read_from(&mut rest)?.0);
+        }
+        Ok(Self(data))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MapIcon {
+    pub direction_and_type: i8, // direction << 4 | type
+    pub x: i8,
+    pub z: i8,
+}
+
+impl Encoder for MapIcon {
+    fn write_to(&self, w: &mut impl std::io::Write) -> crate::net::Result<()> {
+        self.direction_and_type.write_to(w)?;
+        self.x.write_to(w)?;
+        self.z.write_to(w)
+    }
+}
+
+impl Decoder for MapIcon {
+    fn read_from(r: &mut impl std::io::Read) -> crate::net::Result<Self> {
+        Ok(Self {
+            direction_and_type: i8::read_from(r)?,
+            x: i8::read_from(r)?,
+            z: i8::read_from(r)?,
+        })
+    }
+}
+
+/// The colors of a map that changed, `None` if only the icons changed.
+#[derive(Debug, Clone)]
+pub struct MapUpdate {
+    pub data: Option<MapUpdateData>,
+}
+
+impl Encoder for MapUpdate {
+    fn write_to(&self, w: &mut impl std::io::Write) -> crate::net::Result<()> {
+        match &self.data {
+            Some(data) => data.write_to(w),
+            // No columns means there is nothing else
+            None => 0u8.write_to(w),
+        }
+    }
+}
+
+impl Decoder for MapUpdate {
+    fn read_from(r: &mut impl std::io::Read) -> crate::net::Result<Self> {
+        Self::read_with(r, &Limits::default())
+    }
+
+    fn read_with(r: &mut impl std::io::Read, limits: &Limits) -> crate::net::Result<Self> {
+        let data = match NonZeroU8::new(u8::read_from(r)?) {
+            Some(columns) => Some(MapUpdateData::read_rest(columns, r, limits)?),
+            None => None,
+        };
+        Ok(Self { data })
+    }
+}
+
+/// A rectangle of map colors, `data` is the colors row by row and `rows` long.
+/// The number of columns isn't stored but follows from the length of `data`.
+#[derive(Debug, Clone)]
+pub struct MapUpdateData {
+    pub rows: i8,
+    pub x: i8,
+    pub z: i8,
+    pub data: Vec<u8>,
+}
+
+impl MapUpdateData {
+    /// The width of the rectangle, `None` if `data` doesn't fill `rows` rows of 1 to 255 columns.
+    pub fn columns(&self) -> Option<NonZeroU8> {
+        let rows = self.rows as u8 as usize;
+        if rows == 0 || !self.data.len().is_multiple_of(rows) {
+            return None;
+        }
+        u8::try_from(self.data.len() / rows)
+            .ok()
+            .and_then(NonZeroU8::new)
+    }
+
+    /// Reads the fields after `columns`, which has been read already.
+    fn read_rest(
+        columns: NonZeroU8,
+        r: &mut impl std::io::Read,
+        limits: &Limits,
+    ) -> crate::net::Result<Self> {
+        let update = Self {
+            rows: i8::read_from(r)?,
+            x: i8::read_from(r)?,
+            z: i8::read_from(r)?,
+            data: VarIntPrefixedArray::read_with(r, limits)?.into(),
+        };
+        if update.columns() != Some(columns) {
+            return Err(crate::net::Error::Invalid(format!(
+                "map update of {} columns and {} rows has {} colors",
+                columns,
+                update.rows as u8,
+                update.data.len()
+            )));
+        }
+        Ok(update)
+    }
+}
+
+impl Encoder for MapUpdateData {
+    fn write_to(&self, w: &mut impl std::io::Write) -> crate::net::Result<()> {
+        let columns = self.columns().ok_or_else(|| {
+            crate::net::Error::Invalid(format!(
+                "{} map colors don't fill {} rows",
+                self.data.len(),
+                self.rows as u8
+            ))
+        })?;
+        columns.get().write_to(w)?;
+        self.rows.write_to(w)?;
+        self.x.write_to(w)?;
+        self.z.write_to(w)?;
+        VarIntPrefixedArray::from(self.data.as_slice()).write_to(w)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Statistic {
+    pub name: String,
+    pub value: i32,
+}
+
+impl Encoder for Statistic {
+    fn write_to(&self, w: &mut impl std::io::Write) -> crate::net::Result<()> {
+        self.name.write_to(w)?;
+        VarInt(self.value).write_to(w)
+    }
+}
+
+impl Decoder for Statistic {
+    fn read_from(r: &mut impl std::io::Read) -> crate::net::Result<Self> {
+        Ok(Self {
+            name: String::read_from(r)?,
+            value: VarInt::read_from(r)?.0,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlayerListAddPlayer {
+    pub uuid: Uuid,
+    pub name: String,
+    pub properties: Vec<PlayerProperty>,
+    pub gamemode: i32,
+    pub ping: i32,
+    pub display_name: Option<String>, // to be replaced with a Chat type
+}
+
+impl Encoder for PlayerListAddPlayer {
+    fn write_to(&self, w: &mut impl std::io::Write) -> crate::net::Result<()> {
+        self.uuid.write_to(w)?;
+        self.name.write_to(w)?;
+        VarIntPrefixedArray::from(self.properties.as_slice()).write_to(w)?;
+        VarInt(self.gamemode).write_to(w)?;
+        VarInt(self.ping).write_to(w)?;
+        self.display_name.write_to(w)
+    }
+}
+
+impl Decoder for PlayerListAddPlayer {
+    fn read_from(r: &mut impl std::io::Read) -> crate::net::Result<Self> {
+        Self::read_with(r, &Limits::default())
+    }
+
+    fn read_with(r: &mut impl std::io::Read, limits: &Limits) -> crate::net::Result<Self> {
+        Ok(Self {
+            uuid: Uuid::read_from(r)?,
+            name: String::read_from(r)?,
+            properties: VarIntPrefixedArray::read_with(r, limits)?.into(),
+            gamemode: VarInt::read_from(r)?.0,
+            ping: VarInt::read_from(r)?.0,
+            display_name: Option::<String>::read_from(r)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlayerProperty {
+    pub name: String,
+    pub value: String,
+    pub signature: Option<String>,
+}
+
+impl Encoder for PlayerProperty {
+    fn write_to(&self, w: &mut impl std::io::Write) -> crate::net::Result<()> {
+        self.name.write_to(w)?;
+        self.value.write_to(w)?;
+        self.signature.write_to(w)
+    }
+}
+
+impl Decoder for PlayerProperty {
+    fn read_from(r: &mut impl std::io::Read) -> crate::net::Result<Self> {
+        Ok(Self {
+            name: String::read_from(r)?,
+            value: String::read_from(r)?,
+            signature: Option::<String>::read_from(r)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlayerListUpdateGamemode {
+    pub uuid: Uuid,
+    pub gamemode: i32,
+}
+
+impl Encoder for PlayerListUpdateGamemode {
+    fn write_to(&self, w: &mut impl std::io::Write) -> crate::net::Result<()> {
+        self.uuid.write_to(w)?;
+        VarInt(self.gamemode).write_to(w)
+    }
+}
+
+impl Decoder for PlayerListUpdateGamemode {
+    fn read_from(r: &mut impl std::io::Read) -> crate::net::Result<Self> {
+        Ok(Self {
+            uuid: Uuid::read_from(r)?,
+            gamemode: VarInt::read_from(r)?.0,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlayerListUpdateLatency {
+    pub uuid: Uuid,
+    pub ping: i32, // milliseconds
+}
+
+impl Encoder for PlayerListUpdateLatency {
+    fn write_to(&self, w: &mut impl std::io::Write) -> crate::net::Result<()> {
+        self.uuid.write_to(w)?;
+        VarInt(self.ping).write_to(w)
+    }
+}
+
+impl Decoder for PlayerListUpdateLatency {
+    fn read_from(r: &mut impl std::io::Read) -> crate::net::Result<Self> {
+        Ok(Self {
+            uuid: Uuid::read_from(r)?,
+            ping: VarInt::read_from(r)?.0,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlayerListUpdateDisplayName {
+    pub uuid: Uuid,
+    pub display_name: Option<String>, // to be replaced with a Chat type
+}
+
+impl Encoder for PlayerListUpdateDisplayName {
+    fn write_to(&self, w: &mut impl std::io::Write) -> crate::net::Result<()> {
+        self.uuid.write_to(w)?;
+        self.display_name.write_to(w)
+    }
+}
+
+impl Decoder for PlayerListUpdateDisplayName {
+    fn read_from(r: &mut impl std::io::Read) -> crate::net::Result<Self> {
+        Ok(Self {
+            uuid: Uuid::read_from(r)?,
+            display_name: Option::<String>::read_from(r)?,
+        })
+    }
+}
+
+packet_enum! {
+    Clientbound(Play) {
+        KeepAlive,
+        JoinGame,
+        ChatMessage,
+        TimeUpdate,
+        EntityEquipment,
+        SpawnPosition,
+        UpdateHealth,
+        Respawn,
+        PlayerPositionAndLook,
+        HeldItemChange,
+        UseBed,
+        Animation,
+        SpawnPlayer,
+        CollectItem,
+        SpawnObject,
+        SpawnMob,
+        SpawnPainting,
+        SpawnExperienceOrb,
+        EntityVelocity,
+        DestroyEntities,
+        Entity,
+        EntityRelativeMove,
+        EntityLook,
+        EntityLookAndRelativeMove,
+        EntityTeleport,
+        EntityHeadLook,
+        UpdateEntityStatus,
+        AttachEntity,
+        UpdateEntityMetaData,
+        EntityEffect,
+        RemoveEntityEffect,
+        SetExperience,
+        EntityProperties,
+        ChunkData,
+        MultiBlockChange,
+        BlockChange,
+        BlockAction,
+        BlockBreakAnimation,
+        MapChunkBulk,
+        Explosion,
+        Effect,
+        SoundEffect,
+        Particle,
+        ChangeGameState,
+        SpawnGlobalEntity,
+        OpenWindow,
+        CloseWindow,
+        SetSlot,
+        WindowItems,
+        WindowProperty,
+        ConfirmTransaction,
+        UpdateSign,
+        Map,
+        UpdateBlockEntity,
+        OpenSignEditor,
+        Statistics,
+        PlayerListItem,
+        PlayerAbilities,
+        TabComplete,
+        ScoreboardObjective,
+        UpdateScore,
+        DisplayScoreboard,
+        Teams,
+        PluginMessage,
+        Disconnect,
+        ServerDifficulty,
+        CombatEvent,
+        Camera,
+        WorldBorder,
+        Title,
+        SetCompression,
+        PlayerListHeaderAndFooter,
+        ResourcePackSend,
+        UpdateEntityNbt,
+    }
+}
@@ -1 +1,157 @@
+use crate::p47::enums::*;
+use crate::p47::inv::Slot;
+use crate::{packet_enum, packets};
 
+packets! {
+    KeepAlive(0x00) {
+        id VarInt;
+    },
+    ChatMessage(0x01) {
+        message String; // max 100 characters
+    },
+    UseEntity(0x02) {
+        target VarInt;
+        kind UseEntityKind;
+    }
+    Player(0x03) {
+        on_ground bool;
+    }
+    PlayerPosition(0x04) {
+        x f64;
+        feet_y f64;
+        z f64;
+        on_ground bool;
+    }
+    PlayerLook(0x05) {
+        yaw f32;
+        pitch f32;
+        on_ground bool;
+    }
+    PlayerPositionAndLook(0x06) {
+        x f64;
+        feet_y f64;
+        z f64;
+        yaw f32;
+        pitch f32;
+        on_ground bool;
+    }
+    PlayerDigging(0x07) {
+        status DiggingStatus;
+        location Position;
+        face i8;
+    }
+    PlayerBlockPlacement(0x08) {
+        location Position;
+        face i8;
+        held_item Slot;
+        cursor_x i8;
+        cursor_y i8;
+        cursor_z i8;
+    }
+    HeldItemChange(0x09) {
+        slot i16; // 0-8
+    }
+    Animation(0x0A) {}
+    EntityAction(0x0B) {
+        eid VarInt;
+        action_id VarInt;
+        action_parameter VarInt; // only used by horse jump boost
+    }
+    SteerVehicle(0x0C) {
+        sideways f32; // positive to the left
+        forward f32;
+        flags u8; // 0x1: jump, 0x2: unmount
+    }
+    CloseWindow(0x0D) {
+        window_id u8;
+    }
+    ClickWindow(0x0E) {
+        window_id u8;
+        slot i16;
+        button i8;
+        action_number i16;
+        mode i8;
+        clicked_item Slot;
+    }
+    ConfirmTransaction(0x0F) {
+        window_id i8;
+        action_number i16;
+        accepted bool;
+    }
+    CreativeInventoryAction(0x10) {
+        slot i16;
+        clicked_item Slot;
+    }
+    EnchantItem(0x11) {
+        window_id i8;
+        enchantment i8; // position of the enchantment in the table, starting at 0
+    }
+    UpdateSign(0x12) {
+        location Position;
+        line_1 String;
+        line_2 String;
+        line_3 String;
+        line_4 String;
+    }
+    PlayerAbilities(0x13) {
+        flags i8; // Bit field
+        flying_speed f32;
+        walking_speed f32;
+    }
+    TabComplete(0x14) {
+        text String;
+        looked_at_block Option<Position>;
+    }
+    ClientSettings(0x15) {
+        locale String;
+        view_distance i8;
+        chat_mode i8; // 0 = enabled, 1 = commands only, 2 = hidden
+        chat_colors bool;
+        displayed_skin_parts u8; // Bit mask
+    }
+    ClientStatus(0x16) {
+        action_id VarInt; // 0 = perform respawn, 1 = request stats, 2 = taking inventory achievement
+    }
+    PluginMessage(0x17) {
+        channel String;
+        data LengthInferredByteArray;
+    }
+    Spectate(0x18) {
+        target_player Uuid;
+    }
+    ResourcePackStatus(0x19) {
+        hash String;
+        result VarInt; // 0 = loaded, 1 = declined, 2 = failed, 3 = accepted
+    }
+}
+
+packet_enum! {
+    Serverbound(Play) {
+        KeepAlive,
+        ChatMessage,
+        UseEntity,
+        Player,
+        PlayerPosition,
+        PlayerLook,
+        PlayerPositionAndLook,
+        PlayerDigging,
+        PlayerBlockPlacement,
+        HeldItemChange,
+        Animation,
+        EntityAction,
+        SteerVehicle,
+        CloseWindow,
+        ClickWindow,
+        ConfirmTransaction,
+        CreativeInventoryAction,
+        EnchantItem,
+        UpdateSign,
+        PlayerAbilities,
+        TabComplete,
+        ClientSettings,
+        ClientStatus,
+        PluginMessage,
+        Spectate,
+        ResourcePackStatus,
+    }
+}
@@ -1,4 +1,4 @@
-use crate::packets;
+use crate::{packet_enum, packets};
 
 packets! {
     Response(0x00) {
@@ -8,3 +8,10 @@ packets! {
         payload i64
     }
 }
+
+packet_enum! {
+    Clientbound(Status) {
+        Response,
+        Pong,
+    }
+}
@@ -1,2 +1,5 @@
 pub mod clientbound;
 pub mod serverbound;
+
+pub use clientbound::Clientbound;
+pub use serverbound::Serverbound;
@@ -1,4 +1,4 @@
-use crate::packets;
+use crate::{packet_enum, packets};
 
 packets! {
     Request(0x00) {},
@@ -6,3 +6,10 @@ packets! {
         payload i64
     }
 }
+
+packet_enum! {
+    Serverbound(Status) {
+        Request,
+        Ping,
+    }
+}
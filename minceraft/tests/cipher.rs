@@ -0,0 +1,281 @@
+use aes::Aes128;
+use cfb8::{
+    cipher::{AsyncStreamCipher, NewCipher},
+    Cfb8,
+};
+use minceraft::net::{
+    cipher::{CipherReader, CipherWriter, WRITE_BUFFER_SIZE},
+    conn::Conn,
+    packet::RawPacket,
+    types::VarInt,
+};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+const KEY: [u8; 16] = *b"0123456789abcdef";
+
+fn plaintext(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 % 256) as u8).collect()
+}
+
+/// Encrypts `data` in one go, which is what the adapters have to match no matter how it is split up.
+fn reference(data: &[u8]) -> Vec<u8> {
+    let mut data = data.to_vec();
+    Cfb8::<Aes128>::new_from_slices(&KEY, &KEY)
+        .unwrap()
+        .encrypt(&mut data);
+    data
+}
+
+/// The chunk sizes used to split up reads and writes.
+fn sizes() -> impl Iterator<Item = usize> {
+    [1, 7, 16, 3, 100, 2, 33, 1, 500].into_iter().cycle()
+}
+
+/// Hands out at most a few bytes per read.
+struct Trickle<'a> {
+    data: &'a [u8],
+    sizes: Box<dyn Iterator<Item = usize>>,
+}
+
+impl AsyncRead for Trickle<'_> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let len = self
+            .sizes
+            .next()
+            .unwrap()
+            .min(buf.remaining())
+            .min(self.data.len());
+        buf.put_slice(&self.data[..len]);
+        self.data = &self.data[len..];
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Accepts at most a few bytes per write.
+struct TrickleWriter {
+    data: Vec<u8>,
+    sizes: Box<dyn Iterator<Item = usize>>,
+}
+
+impl AsyncWrite for TrickleWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let len = self.sizes.next().unwrap().min(buf.len());
+        self.data.extend_from_slice(&buf[..len]);
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[tokio::test]
+async fn reader_decrypts_only_new_bytes() {
+    let data = plaintext(10_000);
+    let encrypted = reference(&data);
+    let mut reader = CipherReader::new(Trickle {
+        data: &encrypted,
+        sizes: Box::new(sizes()),
+    });
+    reader.enable_encryption(&KEY).unwrap();
+
+    // The same ReadBuf is filled by many reads before it is drained, so old bytes must be left alone
+    let mut decrypted = Vec::new();
+    let mut storage = [0u8; 1024];
+    let mut buf = ReadBuf::new(&mut storage);
+    loop {
+        let before = buf.filled().len();
+        std::future::poll_fn(|cx| Pin::new(&mut reader).poll_read(cx, &mut buf))
+            .await
+            .unwrap();
+        let done = buf.filled().len() == before;
+        if done || buf.remaining() == 0 {
+            decrypted.extend_from_slice(buf.filled());
+            buf.clear();
+        }
+        if done {
+            break;
+        }
+    }
+    assert_eq!(decrypted, data);
+}
+
+#[tokio::test]
+async fn writer_encrypts_partial_writes() {
+    let data = plaintext(10_000);
+    let mut writer = CipherWriter::new(TrickleWriter {
+        data: Vec::new(),
+        sizes: Box::new(sizes()),
+    });
+    writer.enable_encryption(&KEY).unwrap();
+
+    let mut rest = data.as_slice();
+    for (i, size) in sizes().enumerate() {
+        if rest.is_empty() {
+            break;
+        }
+        let written = writer.write(&rest[..size.min(rest.len())]).await.unwrap();
+        rest = &rest[written..];
+        if i % 3 == 0 {
+            writer.flush().await.unwrap();
+            assert_eq!(writer.buffered(), 0);
+        }
+    }
+    writer.flush().await.unwrap();
+    assert_eq!(writer.get_ref().data, reference(&data));
+}
+
+#[tokio::test]
+async fn writer_drains_large_writes() {
+    let data = plaintext(WRITE_BUFFER_SIZE * 3);
+    let (client, mut server) = io::duplex(4096);
+    let mut writer = CipherWriter::new(client);
+    writer.enable_encryption(&KEY).unwrap();
+
+    // The duplex only holds 4K, so this only finishes if the writer empties its buffer on its own
+    let expected = reference(&data);
+    let read = tokio::spawn(async move {
+        let mut received = vec![0; expected.len()];
+        server.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, expected);
+    });
+    for chunk in data.chunks(1000) {
+        writer.write_all(chunk).await.unwrap();
+        assert!(writer.buffered() < WRITE_BUFFER_SIZE + 1000);
+    }
+    writer.flush().await.unwrap();
+    read.await.unwrap();
+}
+
+#[tokio::test]
+async fn append_keeps_nothing_on_error() {
+    let mut writer = CipherWriter::new(Vec::new());
+    writer.enable_encryption(&KEY).unwrap();
+    writer
+        .append(|buf| {
+            buf.extend_from_slice(b"abc");
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+    writer
+        .append(|buf| {
+            buf.extend_from_slice(b"garbage");
+            Err(())
+        })
+        .unwrap_err();
+    writer
+        .append(|buf| {
+            buf.extend_from_slice(b"def");
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+    writer.flush().await.unwrap();
+    assert_eq!(writer.get_ref(), &reference(b"abcdef"));
+}
+
+#[tokio::test]
+async fn conn_interleaves_packets_and_raw_bytes() {
+    let (mut a, mut b) = Conn::pair();
+    a.enable_encryption(&KEY).unwrap();
+    b.enable_encryption(&KEY).unwrap();
+    a.set_compression_threshhold(64);
+    b.set_compression_threshhold(64);
+
+    for i in 0..50 {
+        let packet = RawPacket {
+            id: VarInt(i),
+            data: plaintext(i as usize * 13),
+        };
+        a.send_raw_packet(packet).await.unwrap();
+        b.send_raw_packet(RawPacket {
+            id: VarInt(i),
+            data: plaintext(i as usize),
+        })
+        .await
+        .unwrap();
+
+        // Raw bytes go through the same cipher stream as packets
+        a.write_all(&[i as u8; 3]).await.unwrap();
+        a.flush().await.unwrap();
+
+        let received = b.read_packet().await.unwrap();
+        assert_eq!(received.id, VarInt(i));
+        assert_eq!(received.data, plaintext(i as usize * 13));
+
+        let mut raw = [0; 3];
+        for byte in raw.iter_mut() {
+            *byte = b.read_u8().await.unwrap();
+        }
+        assert_eq!(raw, [i as u8; 3]);
+
+        assert_eq!(a.read_packet().await.unwrap().data, plaintext(i as usize));
+    }
+}
+
+/// The other side sends the encryption response and the first encrypted packet in one write,
+/// so the reader has both buffered when encryption is enabled.
+#[cfg(feature = "p47")]
+#[tokio::test]
+async fn encryption_after_read_ahead() {
+    use minceraft::{
+        net::{
+            packet::{Packet, ProtocolState, Side},
+            types::Encoder,
+        },
+        p47::login,
+    };
+
+    let frame = |packet: RawPacket| {
+        let mut body = Vec::new();
+        packet.id.write_to(&mut body).unwrap();
+        body.extend_from_slice(&packet.data);
+        let mut frame = Vec::new();
+        VarInt(body.len() as i32).write_to(&mut frame).unwrap();
+        frame.extend_from_slice(&body);
+        frame
+    };
+    let response = login::serverbound::EncryptionResponse {
+        shared_secret: KEY.to_vec(),
+        verify_token: vec![1, 2, 3, 4],
+    };
+    let mut bytes = frame(response.encode().unwrap());
+    bytes.extend_from_slice(&reference(&frame(RawPacket {
+        id: VarInt(0x42),
+        data: plaintext(300),
+    })));
+
+    let (mut client, server) = io::duplex(4096);
+    let mut conn = Conn::new(server);
+    conn.set_side(Side::Server);
+    conn.set_state(ProtocolState::Login);
+    client.write_all(&bytes).await.unwrap();
+
+    match conn.read_typed().await.unwrap() {
+        login::Serverbound::EncryptionResponse(p) => assert_eq!(p.verify_token, [1, 2, 3, 4]),
+        _ => panic!("expected the encryption response"),
+    }
+    conn.enable_encryption(&KEY).unwrap();
+    // Read ahead ciphertext that is taken for plaintext makes up a length that never arrives
+    let packet = tokio::time::timeout(std::time::Duration::from_secs(5), conn.read_packet())
+        .await
+        .expect("read ahead bytes weren't decrypted")
+        .unwrap();
+    assert_eq!(packet.id, VarInt(0x42));
+    assert_eq!(packet.data, plaintext(300));
+}
@@ -0,0 +1,359 @@
+use bytes::BytesMut;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder};
+use minceraft::net::{
+    codec::{Limits, PacketCodec, MAX_FRAME_LENGTH},
+    compression::{Compressor, Flate2Compressor},
+    conn::Conn,
+    packet::{PreparedPacket, RawPacket},
+    types::{Decoder, Encoder, VarInt, VarIntPrefixedArray},
+    Error, Limit,
+};
+use std::{
+    io::{self, Read, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+const THRESHOLD: i32 = 64;
+
+fn packets() -> Vec<RawPacket> {
+    vec![
+        RawPacket {
+            id: VarInt(0x21),
+            data: (0..64 * 1024).map(|i| (i % 251) as u8).collect(),
+        },
+        RawPacket {
+            id: VarInt(0x02),
+            data: format!(r#"{{"text":"{}"}}"#, "hello world, ".repeat(20)).into_bytes(),
+        },
+        // Random looking data that doesn't compress
+        RawPacket {
+            id: VarInt(0x26),
+            data: (0u32..4096)
+                .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+                .collect(),
+        },
+        RawPacket {
+            id: VarInt(0x00),
+            data: vec![0; 100_000],
+        },
+    ]
+}
+
+/// The id and data of `packet` as they are before compression.
+fn body(packet: &RawPacket) -> Vec<u8> {
+    let mut body = Vec::new();
+    packet.id.write_to(&mut body).unwrap();
+    body.extend_from_slice(&packet.data);
+    body
+}
+
+/// Splits an encoded frame into its data length and the zlib stream.
+fn split_frame(frame: &[u8]) -> (i32, &[u8]) {
+    let mut buf = frame;
+    let len = VarInt::read_from(&mut buf).unwrap().0;
+    assert_eq!(len as usize, buf.len());
+    let data_len = VarInt::read_from(&mut buf).unwrap().0;
+    (data_len, buf)
+}
+
+#[test]
+fn every_level_decodes_with_reference() {
+    for level in 0..=9 {
+        let mut codec = PacketCodec::new(THRESHOLD);
+        codec.set_compression_level(level);
+
+        // The same codec is used for every packet so its compressor state gets reused
+        for packet in packets() {
+            let mut frame = BytesMut::new();
+            codec.encode_frame(&packet, &mut frame).unwrap();
+
+            let (data_len, zlib) = split_frame(&frame);
+            let mut decoded = Vec::new();
+            ZlibDecoder::new(zlib).read_to_end(&mut decoded).unwrap();
+            assert_eq!(data_len as usize, decoded.len(), "level {}", level);
+            assert_eq!(decoded, body(&packet), "level {}", level);
+        }
+    }
+}
+
+#[test]
+fn reference_decodes_identically() {
+    let mut codec = PacketCodec::new(THRESHOLD);
+    for packet in packets() {
+        let body = body(&packet);
+        let mut zlib = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        zlib.write_all(&body).unwrap();
+        let zlib = zlib.finish().unwrap();
+
+        let mut frame = Vec::new();
+        VarInt(body.len() as i32).write_to(&mut frame).unwrap();
+        frame.extend_from_slice(&zlib);
+
+        let decoded = codec.decode_frame(&frame).unwrap();
+        assert_eq!(decoded.id, packet.id);
+        assert_eq!(decoded.data, packet.data);
+    }
+}
+
+#[test]
+fn default_level_matches_reference_output() {
+    let mut codec = PacketCodec::new(THRESHOLD);
+    for packet in packets() {
+        let mut frame = BytesMut::new();
+        codec.encode_frame(&packet, &mut frame).unwrap();
+
+        let mut zlib = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        zlib.write_all(&body(&packet)).unwrap();
+        assert_eq!(split_frame(&frame).1, zlib.finish().unwrap().as_slice());
+    }
+}
+
+#[test]
+fn lower_levels_trade_ratio() {
+    let packet = &packets()[0];
+    let size = |level| {
+        let mut codec = PacketCodec::new(THRESHOLD);
+        codec.set_compression_level(level);
+        let mut frame = BytesMut::new();
+        codec.encode_frame(packet, &mut frame).unwrap();
+        frame.len()
+    };
+    assert!(size(0) > packet.data.len());
+    assert!(size(1) >= size(9));
+}
+
+/// Counts how often the inner compressor is used.
+#[derive(Clone, Default)]
+struct Counting {
+    inner: Flate2Compressor,
+    compressed: Arc<AtomicUsize>,
+    decompressed: Arc<AtomicUsize>,
+}
+
+impl Compressor for Counting {
+    fn compress(&mut self, data: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+        self.compressed.fetch_add(1, Ordering::Relaxed);
+        self.inner.compress(data, out)
+    }
+
+    fn decompress(&mut self, data: &[u8], max: usize, out: &mut Vec<u8>) -> io::Result<()> {
+        self.decompressed.fetch_add(1, Ordering::Relaxed);
+        self.inner.decompress(data, max, out)
+    }
+}
+
+#[tokio::test]
+async fn custom_compressor_on_conn() {
+    let compressor = Counting::default();
+    let (mut a, mut b) = Conn::pair();
+    a.set_compression_threshhold(THRESHOLD);
+    b.set_compression_threshhold(THRESHOLD);
+    a.set_compressor(compressor.clone());
+    b.set_compressor(compressor.clone());
+    // Also go through the blocking thread pool, which has to hand the compressor back
+    b.set_offload_threshold(32 * 1024);
+
+    let packets = packets();
+    for packet in self::packets() {
+        a.send_raw_packet(packet).await.unwrap();
+    }
+    for packet in &packets {
+        let received = b.read_packet().await.unwrap();
+        assert_eq!(received.id, packet.id);
+        assert_eq!(&received.data, &packet.data);
+    }
+
+    assert_eq!(compressor.compressed.load(Ordering::Relaxed), packets.len());
+    assert_eq!(compressor.decompressed.load(Ordering::Relaxed), packets.len());
+}
+
+#[tokio::test]
+async fn cancelled_offloaded_send() {
+    use futures::FutureExt;
+
+    let compressor = Counting::default();
+    let (mut a, mut b) = Conn::pair();
+    a.set_compression_threshhold(THRESHOLD);
+    b.set_compression_threshhold(THRESHOLD);
+    a.set_compressor(compressor.clone());
+    a.set_offload_threshold(1024);
+    a.enable_stats();
+
+    // Polled once, which hands the packet to the blocking thread pool, and dropped
+    let packets = packets();
+    let mut sent = self::packets().into_iter();
+    let cancelled = a.send_raw_packet(sent.next().unwrap()).now_or_never();
+    assert!(cancelled.is_none());
+    a.send_raw_packet(sent.next().unwrap()).await.unwrap();
+
+    for packet in &packets[..2] {
+        let received = b.read_packet().await.unwrap();
+        assert_eq!(received.id, packet.id);
+        assert_eq!(&received.data, &packet.data);
+    }
+    // The codec came back with its compressor and counters
+    assert_eq!(compressor.compressed.load(Ordering::Relaxed), 2);
+    assert_eq!(a.stats().unwrap().sent.packets, 2);
+}
+
+#[tokio::test]
+async fn pack_with_level() {
+    let mut codec = PacketCodec::new(THRESHOLD);
+    codec.set_compression_level(1);
+    let mut buf = Vec::new();
+    for packet in packets() {
+        packet.pack_with(&mut buf, &mut codec).await.unwrap();
+    }
+
+    let mut r = buf.as_slice();
+    for packet in packets() {
+        let unpacked = RawPacket::unpack(&mut r, THRESHOLD).await.unwrap();
+        assert_eq!(unpacked.id, packet.id);
+        assert_eq!(unpacked.data, packet.data);
+    }
+}
+
+#[tokio::test]
+async fn prepared_packet() {
+    let packet = packets().pop().unwrap();
+    let prepared = PreparedPacket::new(&packet, THRESHOLD).unwrap();
+
+    for _ in 0..2 {
+        let (mut a, mut b) = Conn::pair();
+        a.set_compression_threshhold(THRESHOLD);
+        b.set_compression_threshhold(THRESHOLD);
+        a.send_prepared(&prepared).await.unwrap();
+        let received = b.read_packet().await.unwrap();
+        assert_eq!(received.id, packet.id);
+        assert_eq!(received.data, packet.data);
+    }
+
+    let (mut a, _b) = Conn::pair();
+    match a.send_prepared(&prepared).await {
+        Err(Error::ThresholdMismatch { prepared, conn }) => {
+            assert_eq!(prepared, THRESHOLD);
+            assert_eq!(conn, -1);
+        }
+        r => panic!("expected a threshold mismatch, got {:?}", r),
+    }
+}
+
+/// A compressed frame without its length prefix that declares `declared` bytes but inflates to `data`.
+fn compressed_frame(declared: i32, data: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::new();
+    VarInt(declared).write_to(&mut frame).unwrap();
+    let mut encoder = ZlibEncoder::new(frame, flate2::Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[test]
+fn frame_length_limit() {
+    let mut codec = PacketCodec::new(THRESHOLD);
+    let mut len = Vec::new();
+    VarInt(MAX_FRAME_LENGTH as i32 + 1)
+        .write_to(&mut len)
+        .unwrap();
+    let mut src = BytesMut::from(len.as_slice());
+    match codec.next_frame(&mut src) {
+        Err(Error::TooLarge {
+            limit: Limit::Frame,
+            len,
+            max: MAX_FRAME_LENGTH,
+        }) => assert_eq!(len, MAX_FRAME_LENGTH + 1),
+        other => panic!("expected the frame limit, got {:?}", other),
+    }
+
+    codec.set_limits(Limits {
+        max_frame_length: 100,
+        ..Limits::default()
+    });
+    let mut src = BytesMut::from(&[101, 0][..]);
+    assert!(matches!(
+        codec.next_frame(&mut src),
+        Err(Error::TooLarge {
+            limit: Limit::Frame,
+            len: 101,
+            max: 100,
+        })
+    ));
+    // A frame right at the limit is fine
+    let mut src = BytesMut::from(&[100][..]);
+    src.extend_from_slice(&[0; 100]);
+    assert_eq!(codec.next_frame(&mut src).unwrap().unwrap().len(), 100);
+}
+
+#[test]
+fn data_length_limit() {
+    let mut codec = PacketCodec::new(THRESHOLD);
+    codec.set_limits(Limits {
+        max_data_length: 1000,
+        ..Limits::default()
+    });
+    // Checked before anything is inflated
+    let frame = compressed_frame(5000, &[0; 5000]);
+    assert!(matches!(
+        codec.decode_frame(&frame),
+        Err(Error::TooLarge {
+            limit: Limit::Data,
+            len: 5000,
+            max: 1000,
+        })
+    ));
+
+    let frame = compressed_frame(1000, &[0; 1000]);
+    assert_eq!(codec.decode_frame(&frame).unwrap().data.len(), 999);
+}
+
+#[test]
+fn data_length_mismatch() {
+    let mut codec = PacketCodec::new(THRESHOLD);
+
+    let frame = compressed_frame(300, &[0; 200]);
+    assert!(matches!(
+        codec.decode_frame(&frame),
+        Err(Error::DataLengthMismatch {
+            declared: 300,
+            actual: 200,
+        })
+    ));
+
+    // A bomb that inflates far past what it declares is only inflated by one byte more
+    let frame = compressed_frame(100, &vec![0; 10 * 1024 * 1024]);
+    assert!(frame.len() < 20 * 1024);
+    assert!(matches!(
+        codec.decode_frame(&frame),
+        Err(Error::DataLengthMismatch {
+            declared: 100,
+            actual: 101,
+        })
+    ));
+}
+
+#[test]
+fn array_length_limit() {
+    let mut data = Vec::new();
+    VarIntPrefixedArray::from(vec![7u8; 5])
+        .write_to(&mut data)
+        .unwrap();
+
+    let read = |max| {
+        let limits = Limits {
+            max_array_length: max,
+            ..Limits::default()
+        };
+        VarIntPrefixedArray::<u8>::read_with(&mut data.as_slice(), &limits)
+    };
+    assert!(matches!(
+        read(4),
+        Err(Error::TooLarge {
+            limit: Limit::Array,
+            len: 5,
+            max: 4,
+        })
+    ));
+    assert_eq!(read(5).unwrap().0.len(), 5);
+}
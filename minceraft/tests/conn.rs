@@ -0,0 +1,307 @@
+use minceraft::net::{
+    conn::{Conn, FlushPolicy},
+    keep_alive::KeepAliveOptions,
+    packet::{Direction, Packet, ProtocolState, RawPacket, Side},
+    types::{Uuid, VarInt},
+    Error,
+};
+use minceraft::p47::{enums::ChatPosition, play, status};
+use std::time::Duration;
+
+#[tokio::test]
+async fn send_in_wrong_state() {
+    let (mut client, _server) = Conn::pair();
+    match client.send_packet(status::serverbound::Request {}).await {
+        Err(Error::WrongState {
+            state, conn_state, ..
+        }) => {
+            assert_eq!(state, ProtocolState::Status);
+            assert_eq!(conn_state, ProtocolState::Handshaking);
+        }
+        r => panic!("expected the wrong state, got {:?}", r),
+    }
+}
+
+#[tokio::test]
+async fn send_in_wrong_direction() {
+    let (_client, mut server) = Conn::pair();
+    server.set_state(ProtocolState::Status);
+    match server.send_packet(status::serverbound::Request {}).await {
+        Err(Error::WrongDirection {
+            direction,
+            conn_direction,
+            ..
+        }) => {
+            assert_eq!(direction, Direction::Serverbound);
+            assert_eq!(conn_direction, Direction::Clientbound);
+        }
+        r => panic!("expected the wrong direction, got {:?}", r),
+    }
+}
+
+#[tokio::test]
+async fn read_in_wrong_state() {
+    let (mut client, mut server) = Conn::pair();
+    client.set_state(ProtocolState::Status);
+    client
+        .send_packet(status::serverbound::Request {})
+        .await
+        .unwrap();
+    match server.read_typed::<status::Serverbound>().await {
+        Err(Error::WrongState {
+            state, conn_state, ..
+        }) => {
+            assert_eq!(state, ProtocolState::Status);
+            assert_eq!(conn_state, ProtocolState::Handshaking);
+        }
+        r => panic!("expected the wrong state, got {:?}", r),
+    }
+    // The packet wasn't read
+    server.set_state(ProtocolState::Status);
+    assert!(matches!(
+        server.read_typed().await.unwrap(),
+        status::Serverbound::Request(_)
+    ));
+}
+
+#[tokio::test]
+async fn read_in_wrong_direction() {
+    let (mut client, _server) = Conn::pair();
+    client.set_state(ProtocolState::Status);
+    match client.read_typed::<status::Serverbound>().await {
+        Err(Error::WrongDirection {
+            direction,
+            conn_direction,
+            ..
+        }) => {
+            assert_eq!(direction, Direction::Serverbound);
+            assert_eq!(conn_direction, Direction::Clientbound);
+        }
+        r => panic!("expected the wrong direction, got {:?}", r),
+    }
+}
+
+#[tokio::test]
+async fn keep_alive() {
+    let (mut client, mut server) = Conn::pair();
+    client.set_state(ProtocolState::Play);
+    server.set_state(ProtocolState::Play);
+    client.enable_keep_alive(KeepAliveOptions::default());
+    server.enable_keep_alive(KeepAliveOptions {
+        interval: Duration::from_millis(10),
+        ..Default::default()
+    });
+
+    // The keep alive is answered inside the read and never returned
+    let client = tokio::spawn(async move { client.read_packet().await.unwrap() });
+    while server.latency().is_none() {
+        tokio::time::timeout(Duration::from_millis(50), server.read_packet())
+            .await
+            .unwrap_err();
+    }
+    server
+        .send_packet(play::clientbound::ChatMessage {
+            json: "{}".into(),
+            position: ChatPosition::ChatBox,
+        })
+        .await
+        .unwrap();
+    assert_eq!(client.await.unwrap().id, play::clientbound::ChatMessage::ID);
+}
+
+#[tokio::test]
+async fn reported_ping() {
+    use minceraft::p47::play::clientbound::{
+        PlayerListAction, PlayerListItem, PlayerListUpdateLatency,
+    };
+
+    let uuid = Uuid::from_u128(1);
+    let (mut client, mut server) = Conn::pair();
+    client.set_state(ProtocolState::Play);
+    server.set_state(ProtocolState::Play);
+    client.set_side(Side::Client);
+    client.enable_keep_alive(KeepAliveOptions {
+        uuid: Some(uuid),
+        ..Default::default()
+    });
+
+    for (uuid, ping) in [(Uuid::from_u128(2), 10), (uuid, 42)] {
+        server
+            .send_packet(PlayerListItem {
+                action: PlayerListAction::UpdateLatency {
+                    players: vec![PlayerListUpdateLatency { uuid, ping }],
+                },
+            })
+            .await
+            .unwrap();
+    }
+    // The list is still passed on
+    client.read_packet().await.unwrap();
+    assert_eq!(client.reported_ping(), None);
+    client.read_packet().await.unwrap();
+    assert_eq!(client.reported_ping(), Some(Duration::from_millis(42)));
+    // Clients can't time keep alives, so they go by what the server measured
+    assert!(client.latency().is_some());
+    assert_eq!(client.latency(), client.reported_ping());
+}
+
+#[tokio::test(start_paused = true)]
+async fn read_timeout() {
+    let (_client, mut server) = Conn::pair();
+    server.set_read_timeout(Some(Duration::from_secs(10)));
+    let start = tokio::time::Instant::now();
+    let e = server.read_packet().await.unwrap_err();
+    assert!(matches!(e, Error::Timeout));
+    assert!(e.is_timeout());
+    // The timer rounds up to the next millisecond
+    assert!(start.elapsed() >= Duration::from_secs(10));
+    assert!(start.elapsed() < Duration::from_secs(11));
+}
+
+#[tokio::test(start_paused = true)]
+async fn read_timeout_with_keep_alive() {
+    let (mut client, mut server) = Conn::pair();
+    client.set_state(ProtocolState::Play);
+    server.set_state(ProtocolState::Play);
+    server.set_side(Side::Server);
+    // Sending keep alives wakes the read up in between, which mustn't restart the read timeout
+    server.enable_keep_alive(KeepAliveOptions {
+        interval: Duration::from_secs(3),
+        timeout: Duration::from_secs(60),
+        ..Default::default()
+    });
+    server.set_read_timeout(Some(Duration::from_secs(5)));
+
+    let start = tokio::time::Instant::now();
+    let e = server.read_packet().await.unwrap_err();
+    assert!(matches!(e, Error::Timeout));
+    assert!(start.elapsed() >= Duration::from_secs(5));
+    assert!(start.elapsed() < Duration::from_secs(6));
+    // The client never answered, but the keep alive was sent
+    client.set_side(Side::Client);
+    assert_eq!(
+        client.read_packet().await.unwrap().id,
+        play::clientbound::KeepAlive::ID
+    );
+}
+
+#[tokio::test]
+async fn stats() {
+    let (mut client, mut server) = Conn::pair();
+    client.set_state(ProtocolState::Play);
+    server.set_state(ProtocolState::Play);
+    client.set_compression_threshhold(256);
+    server.set_compression_threshhold(256);
+    client.enable_stats();
+    server.enable_stats();
+
+    let small = play::clientbound::ChatMessage {
+        json: "{}".into(),
+        position: ChatPosition::ChatBox,
+    };
+    let large = play::clientbound::ChatMessage {
+        json: "a".repeat(1000),
+        position: ChatPosition::ChatBox,
+    };
+    server.send_packet(small).await.unwrap();
+    server.send_packet(large).await.unwrap();
+    client.read_packet().await.unwrap();
+    client.read_packet().await.unwrap();
+
+    let sent = server.stats().unwrap().sent;
+    assert_eq!(sent.packets, 2);
+    assert_eq!(sent.compressed_packets, 1);
+    assert_eq!(sent.packets_by_id[&play::clientbound::ChatMessage::ID.0], 2);
+    // The id, the string length and the position around both messages
+    assert_eq!(
+        sent.uncompressed_bytes,
+        (1 + 1 + 2 + 1) + (1 + 2 + 1000 + 1)
+    );
+    // The small frame is its length, the 0 data length and the body, the large one compressed well
+    assert!(sent.bytes < 5 + 2 + 100);
+    assert!(sent.compression_ratio().unwrap() < 0.2);
+
+    let stats = client.stats().unwrap();
+    assert_eq!(stats.received, sent);
+    assert_eq!(stats.sent.packets, 0);
+}
+
+#[tokio::test]
+async fn split_and_reunite() {
+    let (client, mut server) = Conn::pair();
+    // Echoes three packets, compressing from the second one on
+    let echo = tokio::spawn(async move {
+        for i in 0..3 {
+            if i == 1 {
+                server.set_compression_threshhold(64);
+            }
+            let packet = server.read_packet().await.unwrap();
+            server.send_raw_packet(packet).await.unwrap();
+        }
+        server
+    });
+
+    let (mut reader, mut writer) = client.into_split();
+    let packet = |len| RawPacket {
+        id: VarInt(1),
+        data: vec![7; len],
+    };
+    let (sent, read) = tokio::join!(writer.send_raw_packet(packet(10)), reader.read_packet());
+    sent.unwrap();
+    assert_eq!(read.unwrap().data, [7; 10]);
+
+    // Both halves go through the same threshold, so the reader decompresses the echo
+    writer.set_compression_threshhold(64);
+    assert_eq!(reader.threshold(), 64);
+    let (sent, read) = tokio::join!(writer.send_raw_packet(packet(1000)), reader.read_packet());
+    sent.unwrap();
+    assert_eq!(read.unwrap().data, [7; 1000]);
+
+    reader.set_state(ProtocolState::Status);
+    assert_eq!(writer.state(), ProtocolState::Status);
+
+    let mut conn = reader.reunite(writer).unwrap();
+    assert_eq!(conn.state(), ProtocolState::Status);
+    conn.send_raw_packet(packet(1000)).await.unwrap();
+    assert_eq!(conn.read_packet().await.unwrap().data, [7; 1000]);
+    echo.await.unwrap();
+
+    let (reader, _) = Conn::pair().0.into_split();
+    let (_, writer) = Conn::pair().0.into_split();
+    assert!(matches!(reader.reunite(writer), Err(Error::Reunite)));
+}
+
+#[tokio::test]
+async fn flush_policy() {
+    let (mut client, mut server) = Conn::pair();
+    client.set_state(ProtocolState::Play);
+    server.set_state(ProtocolState::Play);
+    let message = |json: &str| play::clientbound::ChatMessage {
+        json: json.into(),
+        position: ChatPosition::ChatBox,
+    };
+
+    server.set_flush_policy(FlushPolicy::Manual);
+    server.queue_packet(message("1")).await.unwrap();
+    server.send_packet(message("2")).await.unwrap();
+    let read = tokio::time::timeout(Duration::from_millis(50), client.read_packet()).await;
+    assert!(read.is_err(), "a packet arrived before the flush");
+    server.flush().await.unwrap();
+    for json in ["1", "2"] {
+        match client.read_typed().await.unwrap() {
+            play::Clientbound::ChatMessage(p) => assert_eq!(p.json, json),
+            p => panic!("expected ChatMessage, got {:?}", p),
+        }
+    }
+
+    // The next packet that is sent takes the queued ones with it
+    server.set_flush_policy(FlushPolicy::default());
+    server.queue_packet(message("3")).await.unwrap();
+    server.send_packet(message("4")).await.unwrap();
+    for json in ["3", "4"] {
+        match client.read_typed().await.unwrap() {
+            play::Clientbound::ChatMessage(p) => assert_eq!(p.json, json),
+            p => panic!("expected ChatMessage, got {:?}", p),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use futures::FutureExt;
+use minceraft::net::{
+    conn::Conn,
+    dispatch::Dispatcher,
+    packet::{ProtocolState, RawPacket},
+    types::{Encoder, VarInt},
+    Error,
+};
+use minceraft::p47::{login, status};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::io::AsyncWriteExt;
+
+fn frame(id: i32, data: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    VarInt(id).write_to(&mut body).unwrap();
+    body.extend_from_slice(data);
+    let mut frame = Vec::new();
+    VarInt(body.len() as i32).write_to(&mut frame).unwrap();
+    frame.extend(body);
+    frame
+}
+
+#[tokio::test]
+async fn cancel_keeps_packet() {
+    let (stream, mut peer) = tokio::io::duplex(1024);
+    let mut conn = Conn::new(stream);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut dispatcher = Dispatcher::new();
+    let handled = seen.clone();
+    dispatcher.fallback(move |_, packet| {
+        handled.lock().unwrap().push(packet.id);
+        async { Ok(()) }.boxed()
+    });
+
+    // The second packet is only half there when the token is cancelled
+    let second = frame(2, &[7; 100]);
+    peer.write_all(&frame(1, &[])).await.unwrap();
+    peer.write_all(&second[..50]).await.unwrap();
+    let token = dispatcher.cancellation_token();
+    let cancel = async {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        token.cancel();
+    };
+    let (result, ()) = tokio::join!(dispatcher.run(&mut conn), cancel);
+    result.unwrap();
+    assert_eq!(*seen.lock().unwrap(), [VarInt(1)]);
+
+    peer.write_all(&second[50..]).await.unwrap();
+    let packet = conn.read_packet().await.unwrap();
+    assert_eq!(packet.id, VarInt(2));
+    assert_eq!(packet.data, [7; 100]);
+}
+
+#[tokio::test]
+async fn routes_by_state_and_direction() {
+    let (mut client, mut server) = Conn::pair();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut dispatcher = Dispatcher::new();
+    let (request, response, login_start, fallback) =
+        (seen.clone(), seen.clone(), seen.clone(), seen.clone());
+    dispatcher
+        .on::<status::serverbound::Request>(move |conn, _| {
+            request.lock().unwrap().push("request");
+            async move {
+                let response = "{}".to_owned();
+                conn.send_packet(status::clientbound::Response { response })
+                    .await
+            }
+            .boxed()
+        })
+        // Same state and id as `Request`, but the server never reads it
+        .on::<status::clientbound::Response>(move |_, _| {
+            response.lock().unwrap().push("response");
+            async { Ok(()) }.boxed()
+        })
+        // Same id as `Request` in another state
+        .on::<login::serverbound::LoginStart>(move |_, p| {
+            login_start.lock().unwrap().push("login start");
+            assert_eq!(p.name, "Notch");
+            async { Ok(()) }.boxed()
+        })
+        .fallback(move |_, _| {
+            fallback.lock().unwrap().push("fallback");
+            async { Ok(()) }.boxed()
+        });
+
+    client.set_state(ProtocolState::Status);
+    server.set_state(ProtocolState::Status);
+    client
+        .send_packet(status::serverbound::Request {})
+        .await
+        .unwrap();
+    let packet = server.read_packet().await.unwrap();
+    dispatcher.dispatch(&mut server, packet).await.unwrap();
+    // The handler replied through the connection
+    match client.read_typed().await.unwrap() {
+        status::Clientbound::Response(p) => assert_eq!(p.response, "{}"),
+        p => panic!("expected Response, got {:?}", p),
+    }
+
+    client
+        .send_packet(status::serverbound::Ping { payload: 1 })
+        .await
+        .unwrap();
+    let packet = server.read_packet().await.unwrap();
+    dispatcher.dispatch(&mut server, packet).await.unwrap();
+
+    client.set_state(ProtocolState::Login);
+    server.set_state(ProtocolState::Login);
+    client
+        .send_packet(login::serverbound::LoginStart {
+            name: "Notch".into(),
+        })
+        .await
+        .unwrap();
+    let packet = server.read_packet().await.unwrap();
+    dispatcher.dispatch(&mut server, packet).await.unwrap();
+
+    assert_eq!(
+        *seen.lock().unwrap(),
+        ["request", "fallback", "login start"]
+    );
+}
+
+#[tokio::test]
+async fn decode_error_stops_run() {
+    let (mut client, mut server) = Conn::pair();
+    client.set_state(ProtocolState::Status);
+    server.set_state(ProtocolState::Status);
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.on::<status::serverbound::Ping>(|_, _| async { Ok(()) }.boxed());
+
+    // A ping with two of the eight bytes of its payload
+    client
+        .send_raw_packet(RawPacket {
+            id: VarInt(0x01),
+            data: vec![1, 2],
+        })
+        .await
+        .unwrap();
+    match dispatcher.run(&mut server).await {
+        Err(Error::Field { packet, field, .. }) => {
+            assert_eq!((packet, field), ("Ping", "payload"));
+        }
+        r => panic!("expected a field error, got {:?}", r),
+    }
+}
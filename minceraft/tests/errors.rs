@@ -0,0 +1,125 @@
+use minceraft::net::{
+    codec::PacketCodec,
+    conn::Conn,
+    packet::{Direction, Packet, PacketEnum, ProtocolState, RawPacket},
+    server::Server,
+    types::{Encoder, VarInt},
+    Error,
+};
+use minceraft::p47::{enums::HandshakeState, handshaking, login, play, status};
+
+/// The body of a handshake, with `next_state` as a raw VarInt.
+fn handshake(protocol_version: &[u8], next_state: i32) -> RawPacket {
+    let mut data = protocol_version.to_vec();
+    "localhost".to_owned().write_to(&mut data).unwrap();
+    25565u16.write_to(&mut data).unwrap();
+    VarInt(next_state).write_to(&mut data).unwrap();
+    RawPacket {
+        id: handshaking::serverbound::Handshake::ID,
+        data,
+    }
+}
+
+#[test]
+fn var_int_too_long() {
+    let packet = handshake(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01], 2);
+    match handshaking::serverbound::Handshake::decode(packet) {
+        Err(Error::Field {
+            packet,
+            field,
+            source,
+        }) => {
+            assert_eq!((packet, field), ("Handshake", "protocol_version"));
+            assert!(matches!(source.root(), Error::VarIntTooLong));
+        }
+        r => panic!("expected a field error, got {:?}", r),
+    }
+}
+
+#[test]
+fn unknown_discriminant() {
+    let packet = handshake(&[47], 5);
+    let e = handshaking::serverbound::Handshake::decode(packet).unwrap_err();
+    match e.root() {
+        Error::UnknownDiscriminant { enum_name, value } => {
+            assert_eq!(*enum_name, "HandshakeState");
+            assert_eq!(value, "VarInt(5)");
+        }
+        e => panic!("expected an unknown discriminant, got {:?}", e),
+    }
+}
+
+#[test]
+fn bad_zlib() {
+    let mut codec = PacketCodec::new(64);
+    let mut frame = Vec::new();
+    VarInt(100).write_to(&mut frame).unwrap();
+    frame.extend_from_slice(b"this isn't zlib");
+    match codec.decode_frame(&frame) {
+        Err(Error::Compression(_)) => {}
+        r => panic!("expected invalid compressed data, got {:?}", r),
+    }
+}
+
+#[test]
+fn unknown_packet_id() {
+    let packet = RawPacket {
+        id: VarInt(0x7F),
+        data: Vec::new(),
+    };
+    match status::Serverbound::decode(packet) {
+        Err(Error::UnknownPacketId(e)) => {
+            assert_eq!(e.id, VarInt(0x7F));
+            assert!(e.packet_enum.ends_with("status::serverbound::Serverbound"));
+        }
+        r => panic!("expected an unknown packet id, got {:?}", r),
+    }
+}
+
+#[tokio::test]
+async fn unknown_play_packet_id() {
+    let (mut client, mut server) = Conn::pair();
+    client.set_state(ProtocolState::Play);
+    server.set_state(ProtocolState::Play);
+
+    server
+        .send_raw_packet(RawPacket {
+            id: VarInt(0x50),
+            data: vec![1, 2, 3],
+        })
+        .await
+        .unwrap();
+    match client.read_typed::<play::Clientbound>().await {
+        Err(Error::UnknownPacketId(e)) => {
+            assert_eq!(e.id, VarInt(0x50));
+            assert_eq!(e.state, ProtocolState::Play);
+            assert_eq!(e.direction, Direction::Clientbound);
+        }
+        r => panic!("expected an unknown packet id, got {:?}", r),
+    }
+}
+
+#[tokio::test]
+async fn server_rejects_other_protocol_versions() {
+    let (mut client, server) = Conn::pair();
+    let accepted = tokio::spawn(async move { Server::new("{}").accept_conn(server).await });
+
+    client
+        .send_packet(handshaking::serverbound::Handshake {
+            protocol_version: 5,
+            server_address: "localhost".into(),
+            server_port: 25565,
+            next_state: HandshakeState::Login,
+        })
+        .await
+        .unwrap();
+    client.set_state(ProtocolState::Login);
+    match client.read_typed().await.unwrap() {
+        login::Clientbound::Disconnect(p) => assert!(p.reason.contains("Outdated client")),
+        p => panic!("expected Disconnect, got {:?}", p),
+    }
+    assert!(matches!(
+        accepted.await.unwrap(),
+        Err(Error::Invalid(e)) if e.contains("protocol version 5")
+    ));
+}
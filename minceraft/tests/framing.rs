@@ -0,0 +1,154 @@
+use minceraft::net::{
+    codec::MAX_FRAME_LENGTH,
+    conn::Conn,
+    types::{Encoder, VarInt},
+    Error, Limit,
+};
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// A stream that hands out at most `chunk` bytes per read and then ends.
+struct Chunked {
+    data: Vec<u8>,
+    pos: usize,
+    chunk: usize,
+}
+
+impl AsyncRead for Chunked {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let end = self
+            .data
+            .len()
+            .min(self.pos + self.chunk.min(buf.remaining()));
+        buf.put_slice(&self.data[self.pos..end]);
+        self.pos = end;
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn conn(data: Vec<u8>, chunk: usize) -> Conn<Chunked, tokio::io::Sink> {
+    Conn::from_halves(
+        Chunked {
+            data,
+            pos: 0,
+            chunk,
+        },
+        tokio::io::sink(),
+    )
+}
+
+fn var_int(value: i32) -> Vec<u8> {
+    let mut buf = Vec::new();
+    VarInt(value).write_to(&mut buf).unwrap();
+    buf
+}
+
+fn frame(id: i32, data: &[u8]) -> Vec<u8> {
+    let mut body = var_int(id);
+    body.extend_from_slice(data);
+    let mut frame = var_int(body.len() as i32);
+    frame.extend_from_slice(&body);
+    frame
+}
+
+/// Frames with one, two and five byte lengths, the last one padded with continuation bytes.
+fn frames() -> (Vec<u8>, Vec<(i32, Vec<u8>)>) {
+    let packets = vec![
+        (0x01, vec![1, 2]),
+        (0x02, (0..300).map(|i| i as u8).collect()),
+        (0x03, vec![]),
+        (0x04, vec![9]),
+    ];
+    let mut bytes = Vec::new();
+    for (id, data) in &packets[..3] {
+        bytes.extend(frame(*id, data));
+    }
+    bytes.extend([0x82, 0x80, 0x80, 0x80, 0x00, 0x04, 9]);
+    (bytes, packets)
+}
+
+async fn read_all(chunk: usize) {
+    let (bytes, packets) = frames();
+    let mut conn = conn(bytes, chunk);
+    for (id, data) in packets {
+        let packet = conn.read_packet().await.unwrap();
+        assert_eq!(packet.id, VarInt(id));
+        assert_eq!(packet.data, data);
+    }
+    // The stream ended between frames
+    assert!(conn.read_packet().await.unwrap_err().is_eof());
+}
+
+#[tokio::test]
+async fn byte_by_byte() {
+    read_all(1).await;
+}
+
+#[tokio::test]
+async fn split_frames() {
+    read_all(7).await;
+}
+
+#[tokio::test]
+async fn coalesced_frames() {
+    read_all(usize::MAX).await;
+}
+
+#[tokio::test]
+async fn eof_mid_frame() {
+    for chunk in [1, usize::MAX] {
+        let (mut bytes, _) = frames();
+        bytes.pop();
+        let mut conn = conn(bytes, chunk);
+        for _ in 0..3 {
+            conn.read_packet().await.unwrap();
+        }
+        match conn.read_packet().await.unwrap_err() {
+            Error::Io(e) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            e => panic!("expected an unexpected eof, got {:?}", e),
+        }
+    }
+}
+
+#[tokio::test]
+async fn eof_mid_length() {
+    let mut conn = conn(vec![0x80, 0x80], 1);
+    match conn.read_packet().await.unwrap_err() {
+        Error::Io(e) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+        e => panic!("expected an unexpected eof, got {:?}", e),
+    }
+}
+
+#[tokio::test]
+async fn five_byte_length_too_large() {
+    for chunk in [1, usize::MAX] {
+        let mut conn = conn(var_int(1 << 28), chunk);
+        match conn.read_packet().await.unwrap_err() {
+            Error::TooLarge { limit, len, max } => {
+                assert_eq!(limit, Limit::Frame);
+                assert_eq!(len, 1 << 28);
+                assert_eq!(max, MAX_FRAME_LENGTH);
+            }
+            e => panic!("expected the frame to be too large, got {:?}", e),
+        }
+    }
+}
+
+#[tokio::test]
+async fn length_too_long() {
+    for chunk in [1, usize::MAX] {
+        let mut conn = conn(vec![0x80; 6], chunk);
+        assert!(matches!(
+            conn.read_packet().await.unwrap_err(),
+            Error::VarIntTooLong
+        ));
+    }
+}
@@ -0,0 +1,69 @@
+use minceraft::net::{
+    conn::Conn,
+    legacy::{self, LegacyStatus, KICK, PING},
+    server::{Accepted, Server},
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+const STATUS: &str = r#"{
+    "version": {"name": "1.8.9", "protocol": 47},
+    "players": {"max": 20, "online": 3},
+    "description": "§aA Minecraft Server ✓"
+}"#;
+
+fn status() -> LegacyStatus {
+    LegacyStatus {
+        protocol: 47,
+        version: "1.8.9".to_owned(),
+        motd: "§aA Minecraft Server ✓".to_owned(),
+        online: 3,
+        max: 20,
+    }
+}
+
+#[tokio::test]
+async fn answer_and_ping() {
+    let (mut client, server) = Conn::pair();
+    let accepted = tokio::spawn(async move { Server::new(STATUS).accept_conn(server).await });
+    assert_eq!(legacy::ping_conn(&mut client).await.unwrap(), status());
+    assert!(matches!(accepted.await.unwrap(), Ok(Accepted::Status)));
+}
+
+#[tokio::test]
+async fn beta_answer() {
+    let (mut client, server) = Conn::pair();
+    let accepted = tokio::spawn(async move { Server::new(STATUS).accept_conn(server).await });
+    client.write_all(&[PING]).await.unwrap();
+    client.flush().await.unwrap();
+
+    let mut packet = Vec::new();
+    client.read_to_end(&mut packet).await.unwrap();
+    assert!(matches!(accepted.await.unwrap(), Ok(Accepted::Status)));
+    assert_eq!(packet[0], KICK);
+    let units: Vec<u16> = packet[3..]
+        .chunks(2)
+        .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+        .collect();
+    assert_eq!(
+        usize::from(u16::from_be_bytes([packet[1], packet[2]])),
+        units.len()
+    );
+
+    let message = String::from_utf16(&units).unwrap();
+    assert_eq!(message, "A Minecraft Server ✓§3§20");
+    let beta = LegacyStatus::from_kick_message(&message).unwrap();
+    assert_eq!(beta.protocol, -1);
+    assert_eq!(beta.motd, "A Minecraft Server ✓");
+    assert_eq!((beta.online, beta.max), (3, 20));
+}
+
+#[test]
+fn kick_messages() {
+    let status = status();
+    assert_eq!(
+        LegacyStatus::from_kick_message(&status.to_kick_message()).unwrap(),
+        status
+    );
+    assert!(LegacyStatus::from_kick_message("§1\x0047\x001.8.9").is_err());
+    assert!(LegacyStatus::from_kick_message("motd§3").is_err());
+}
@@ -0,0 +1,179 @@
+use minceraft::net::{
+    client::{self, LoginOptions},
+    conn::Conn,
+    packet::ProtocolState,
+    server::{offline_uuid, Accepted, Server, DEFAULT_COMPRESSION_THRESHOLD},
+    Error,
+};
+use minceraft::p47::{
+    enums::{ChatPosition, HandshakeState},
+    handshaking, login, play, PROTOCOL_VERSION,
+};
+
+#[test]
+fn offline_uuids() {
+    // What vanilla and Bukkit give these players in offline mode
+    assert_eq!(
+        offline_uuid("Notch").to_string(),
+        "b50ad385-829d-3141-a216-7e7d7539ba7f"
+    );
+    assert_eq!(offline_uuid("Notch").get_version_num(), 3);
+    assert_ne!(offline_uuid("Notch"), offline_uuid("notch"));
+}
+
+/// Sends the handshake and login start a client sends.
+async fn start_login<R, W>(conn: &mut Conn<R, W>, name: &str)
+where
+    R: tokio::io::AsyncRead + Unpin,
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    conn.send_packet(handshaking::serverbound::Handshake {
+        protocol_version: PROTOCOL_VERSION,
+        server_address: "localhost".into(),
+        server_port: 25565,
+        next_state: HandshakeState::Login,
+    })
+    .await
+    .unwrap();
+    conn.set_state(ProtocolState::Login);
+    conn.send_packet(login::serverbound::LoginStart { name: name.into() })
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn accept_offline_login() {
+    let (mut client, server) = Conn::pair();
+    let accepted = tokio::spawn(async move { Server::new("{}").accept_conn(server).await });
+
+    start_login(&mut client, "Notch").await;
+    match client.read_typed().await.unwrap() {
+        login::Clientbound::SetCompression(p) => {
+            assert_eq!(p.threshold, DEFAULT_COMPRESSION_THRESHOLD)
+        }
+        p => panic!("expected SetCompression, got {:?}", p),
+    }
+    client.set_compression_threshhold(DEFAULT_COMPRESSION_THRESHOLD);
+    match client.read_typed().await.unwrap() {
+        login::Clientbound::LoginSuccess(p) => {
+            assert_eq!(p.username, "Notch");
+            assert_eq!(p.uuid, "b50ad385-829d-3141-a216-7e7d7539ba7f");
+        }
+        p => panic!("expected LoginSuccess, got {:?}", p),
+    }
+
+    let Accepted::Login(player) = accepted.await.unwrap().unwrap() else {
+        panic!("expected a login");
+    };
+    assert_eq!(player.name, "Notch");
+    assert_eq!(player.uuid, offline_uuid("Notch"));
+    assert_eq!(player.conn.state(), ProtocolState::Play);
+}
+
+#[tokio::test]
+async fn reject_invalid_name() {
+    let (mut client, server) = Conn::pair();
+    let accepted = tokio::spawn(async move { Server::new("{}").accept_conn(server).await });
+
+    start_login(&mut client, "").await;
+    match client.read_typed().await.unwrap() {
+        login::Clientbound::Disconnect(p) => {
+            let reason: serde_json::Value = serde_json::from_str(&p.reason).unwrap();
+            assert_eq!(reason["text"], "Invalid username");
+        }
+        p => panic!("expected Disconnect, got {:?}", p),
+    }
+    assert!(accepted.await.unwrap().is_err());
+}
+
+#[tokio::test]
+async fn client_login() {
+    let (client, server) = Conn::pair();
+    let accepted = tokio::spawn(async move { Server::new("{}").accept_conn(server).await });
+
+    let (mut conn, success) = client::login_conn(
+        client,
+        "localhost",
+        25565,
+        "Notch",
+        &LoginOptions::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(success.username, "Notch");
+    assert_eq!(success.uuid, offline_uuid("Notch").to_string());
+    assert_eq!(conn.state(), ProtocolState::Play);
+
+    let Accepted::Login(mut player) = accepted.await.unwrap().unwrap() else {
+        panic!("expected a login");
+    };
+    assert_eq!(player.name, "Notch");
+
+    // Only decodes if the client took over the threshold from SetCompression
+    let json = "a".repeat(2 * DEFAULT_COMPRESSION_THRESHOLD as usize);
+    player
+        .conn
+        .send_packet(play::clientbound::ChatMessage {
+            json: json.clone(),
+            position: ChatPosition::ChatBox,
+        })
+        .await
+        .unwrap();
+    match conn.read_typed().await.unwrap() {
+        play::Clientbound::ChatMessage(p) => assert_eq!(p.json, json),
+        p => panic!("expected ChatMessage, got {:?}", p),
+    }
+}
+
+#[tokio::test]
+async fn client_disconnected() {
+    let (client, server) = Conn::pair();
+    let accepted = tokio::spawn(async move { Server::new("{}").accept_conn(server).await });
+
+    match client::login_conn(client, "localhost", 25565, "", &LoginOptions::default()).await {
+        Err(Error::Disconnected { reason }) => assert!(reason.contains("Invalid username")),
+        r => panic!(
+            "expected a disconnect, got {:?}",
+            r.map(|(_, success)| success)
+        ),
+    }
+    assert!(matches!(accepted.await.unwrap(), Err(Error::Invalid(_))));
+}
+
+#[cfg(feature = "online")]
+#[test]
+fn debug_leaves_out_private_key() {
+    use minceraft::net::server::{OnlineMode, Server};
+
+    let mut server = Server::new("{}");
+    server.online_mode = Some(OnlineMode::new().unwrap());
+    let debug = format!("{:?}", server);
+    assert!(debug.contains("public_key"));
+    assert!(!debug.contains("RsaPrivateKey"));
+    assert!(!debug.contains("primes"));
+}
+
+#[cfg(feature = "online")]
+#[test]
+fn server_hashes() {
+    use minceraft::net::session::server_hash;
+
+    // The digests from wiki.vg, the sha1 of just the name
+    assert_eq!(
+        server_hash("Notch", &[], &[]),
+        "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48"
+    );
+    assert_eq!(
+        server_hash("jeb_", &[], &[]),
+        "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1"
+    );
+    assert_eq!(
+        server_hash("simon", &[], &[]),
+        "88e16a1019277b15d58faf0541e11910eb756f6"
+    );
+    // Only the concatenation is hashed
+    assert_eq!(
+        server_hash("No", b"tc", b"h"),
+        server_hash("Notch", &[], &[])
+    );
+}
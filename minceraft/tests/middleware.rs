@@ -0,0 +1,143 @@
+use minceraft::net::{
+    conn::Conn,
+    middleware::{typed, Context, Middleware, Pipeline},
+    packet::{Packet, ProtocolState, RawPacket},
+    types::VarInt,
+    Result,
+};
+use minceraft::p47::{enums::ChatPosition, play};
+
+/// Appends its tag to the data of every packet in both directions.
+struct Tag(u8);
+
+impl Middleware for Tag {
+    fn inbound(&mut self, mut packet: RawPacket, _: &mut Context) -> Result<Option<RawPacket>> {
+        packet.data.push(self.0);
+        Ok(Some(packet))
+    }
+
+    fn outbound(&mut self, mut packet: RawPacket, _: &mut Context) -> Result<Option<RawPacket>> {
+        packet.data.push(self.0);
+        Ok(Some(packet))
+    }
+}
+
+/// Answers packet 1 with a packet 2 to the connection and a packet 3 to the reader.
+struct Echo;
+
+impl Middleware for Echo {
+    fn inbound(&mut self, packet: RawPacket, cx: &mut Context) -> Result<Option<RawPacket>> {
+        if packet.id == VarInt(1) {
+            cx.send(packet_with_id(2));
+            cx.receive(packet_with_id(3));
+        }
+        Ok(Some(packet))
+    }
+}
+
+fn packet_with_id(id: i32) -> RawPacket {
+    RawPacket {
+        id: VarInt(id),
+        data: Vec::new(),
+    }
+}
+
+#[tokio::test]
+async fn order() {
+    let (client, mut server) = Conn::pair();
+    let mut pipeline = Pipeline::new(client);
+    pipeline.push(Tag(b'a'));
+    pipeline.push(Tag(b'b'));
+
+    // Read packets go through the middleware in the order it was added, sent packets in reverse
+    server.send_raw_packet(packet_with_id(0)).await.unwrap();
+    assert_eq!(pipeline.read_packet().await.unwrap().data, b"ab");
+    pipeline.send_raw_packet(packet_with_id(0)).await.unwrap();
+    assert_eq!(server.read_packet().await.unwrap().data, b"ba");
+}
+
+#[tokio::test]
+async fn made_up_packets() {
+    let (client, mut server) = Conn::pair();
+    let mut pipeline = Pipeline::new(client);
+    pipeline.push(Tag(b'a'));
+    pipeline.push(Echo);
+    pipeline.push(Tag(b'c'));
+
+    server.send_raw_packet(packet_with_id(1)).await.unwrap();
+    let packet = pipeline.read_packet().await.unwrap();
+    assert_eq!((packet.id, packet.data), (VarInt(1), b"ac".to_vec()));
+    // Made up packets only go through the middleware between the one that made them up and their end
+    let packet = pipeline.read_packet().await.unwrap();
+    assert_eq!((packet.id, packet.data), (VarInt(3), b"c".to_vec()));
+    let packet = server.read_packet().await.unwrap();
+    assert_eq!((packet.id, packet.data), (VarInt(2), b"a".to_vec()));
+}
+
+fn chat(json: &str) -> play::clientbound::ChatMessage {
+    play::clientbound::ChatMessage {
+        json: json.into(),
+        position: ChatPosition::ChatBox,
+    }
+}
+
+#[tokio::test]
+async fn typed_inbound() {
+    let (client, mut server) = Conn::pair();
+    server.set_state(ProtocolState::Play);
+    let mut pipeline = Pipeline::new(client);
+    pipeline.conn_mut().set_state(ProtocolState::Play);
+    pipeline.push(typed(|mut p: play::clientbound::ChatMessage, _| {
+        if p.json == "drop" {
+            return None;
+        }
+        p.json = p.json.to_uppercase();
+        Some(p)
+    }));
+
+    server.send_packet(chat("drop")).await.unwrap();
+    server.send_packet(chat("hi")).await.unwrap();
+    // The id of a chat message, but a string that is longer than the packet
+    server
+        .send_raw_packet(RawPacket {
+            id: play::clientbound::ChatMessage::ID,
+            data: vec![10, b'a'],
+        })
+        .await
+        .unwrap();
+    server.send_raw_packet(packet_with_id(0x03)).await.unwrap();
+
+    match pipeline.read_typed().await.unwrap() {
+        play::Clientbound::ChatMessage(p) => assert_eq!(p.json, "HI"),
+        p => panic!("expected ChatMessage, got {:?}", p),
+    }
+    let packet = pipeline.read_packet().await.unwrap();
+    assert_eq!(packet.id, play::clientbound::ChatMessage::ID);
+    assert_eq!(packet.data, [10, b'a']);
+    let packet = pipeline.read_packet().await.unwrap();
+    assert_eq!((packet.id, packet.data), (VarInt(0x03), Vec::new()));
+}
+
+#[tokio::test]
+async fn typed_outbound_drop() {
+    let (client, mut server) = Conn::pair();
+    server.set_state(ProtocolState::Play);
+    let mut pipeline = Pipeline::new(client);
+    pipeline.conn_mut().set_state(ProtocolState::Play);
+    pipeline.push(typed(|p: play::serverbound::ChatMessage, _| {
+        (!p.message.starts_with('/')).then_some(p)
+    }));
+
+    for message in ["/secret", "hello"] {
+        pipeline
+            .send_packet(play::serverbound::ChatMessage {
+                message: message.into(),
+            })
+            .await
+            .unwrap();
+    }
+    match server.read_typed().await.unwrap() {
+        play::Serverbound::ChatMessage(p) => assert_eq!(p.message, "hello"),
+        p => panic!("expected ChatMessage, got {:?}", p),
+    }
+}
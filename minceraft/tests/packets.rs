@@ -0,0 +1,87 @@
+use minceraft::net::{packet::Packet, Error};
+use minceraft::p47::play::clientbound::{EntityLook, Map, MapUpdate, MapUpdateData};
+
+#[test]
+fn angle_round_trip() {
+    let look = EntityLook {
+        eid: 1,
+        yaw: 270.0,
+        pitch: -90.0,
+        on_ground: true,
+    };
+    let packet = look.encode().unwrap();
+    // The varint entity id and then 3/4 of a turn twice
+    assert_eq!(packet.data[1..3], [192, 192]);
+
+    let look = EntityLook::decode(packet).unwrap();
+    assert_eq!(look.yaw, 270.0);
+    assert_eq!(look.pitch, 270.0);
+
+    for degrees in [0.0, 1.40625, 45.0, 180.0, 358.59375] {
+        let look = EntityLook {
+            eid: 1,
+            yaw: degrees,
+            pitch: degrees - 360.0,
+            on_ground: false,
+        };
+        let look = EntityLook::decode(look.encode().unwrap()).unwrap();
+        assert_eq!(look.yaw, degrees);
+        assert_eq!(look.pitch, degrees);
+    }
+    // Close to a full turn rounds to 0 instead of saturating
+    let look = EntityLook {
+        eid: 1,
+        yaw: 359.9,
+        pitch: 0.0,
+        on_ground: false,
+    };
+    assert_eq!(EntityLook::decode(look.encode().unwrap()).unwrap().yaw, 0.0);
+}
+
+#[test]
+fn map_update_round_trip() {
+    let map = Map {
+        item_damage: 3,
+        scale: 0,
+        icons: Vec::new(),
+        update: MapUpdate { data: None },
+    };
+    let packet = map.encode().unwrap();
+    // item damage, scale, no icons and no columns
+    assert_eq!(packet.data, [3, 0, 0, 0]);
+    assert!(Map::decode(packet).unwrap().update.data.is_none());
+
+    let map = Map {
+        item_damage: 3,
+        scale: 0,
+        icons: Vec::new(),
+        update: MapUpdate {
+            data: Some(MapUpdateData {
+                rows: 1,
+                x: 4,
+                z: 5,
+                data: vec![7, 8],
+            }),
+        },
+    };
+    let packet = map.encode().unwrap();
+    assert_eq!(packet.data, [3, 0, 0, 2, 1, 4, 5, 2, 7, 8]);
+    let data = Map::decode(packet).unwrap().update.data.unwrap();
+    assert_eq!(data.columns().unwrap().get(), 2);
+    assert_eq!((data.rows, data.x, data.z), (1, 4, 5));
+    assert_eq!(data.data, [7, 8]);
+
+    // Three colors don't make a rectangle of two rows
+    let map = Map {
+        update: MapUpdate {
+            data: Some(MapUpdateData {
+                rows: 2,
+                x: 0,
+                z: 0,
+                data: vec![1, 2, 3],
+            }),
+        },
+        ..map
+    };
+    assert!(matches!(map.encode(), Err(Error::Invalid(_))));
+}
@@ -0,0 +1,346 @@
+use minceraft::net::{
+    conn::Conn,
+    packet::{Direction, Packet, ProtocolState, RawPacket, Side},
+    proxy::{Inject, Proxy},
+    server::{Accepted, Server},
+};
+use minceraft::p47::{
+    enums::{ChatPosition, HandshakeState},
+    handshaking, login, play, status, PROTOCOL_VERSION,
+};
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+use tokio::{net::TcpListener, task::JoinHandle};
+
+const STATUS: &str = r#"{"version":{"name":"1.8.9","protocol":47},"players":{"max":1,"online":0},"description":{"text":"upstream"}}"#;
+
+type Seen = Arc<Mutex<Vec<(Direction, ProtocolState, i32)>>>;
+
+/// Starts an upstream server that accepts one client with `server` and hands it to `play`.
+async fn upstream<F, Fut>(server: Server, play: F) -> (SocketAddr, JoinHandle<()>)
+where
+    F: FnOnce(Conn) -> Fut + Send + 'static,
+    Fut: std::future::Future<Output = ()> + Send,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let task = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        match server.accept(stream).await.unwrap() {
+            Accepted::Status => {}
+            Accepted::Login(player) => play(player.conn).await,
+        }
+    });
+    (addr, task)
+}
+
+/// Starts a proxy in front of `upstream` for one client, the hook rewrites chat and drops time updates.
+async fn proxy(
+    upstream: SocketAddr,
+    seen: Seen,
+) -> (SocketAddr, JoinHandle<minceraft::net::Result<()>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let task = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let hook = move |direction, state, packet: RawPacket, inject: &mut Inject| {
+            seen.lock().unwrap().push((direction, state, packet.id.0));
+            match (direction, state) {
+                (Direction::Clientbound, ProtocolState::Play)
+                    if packet.id == play::clientbound::TimeUpdate::ID =>
+                {
+                    None
+                }
+                (Direction::Serverbound, ProtocolState::Play)
+                    if packet.id == play::serverbound::ChatMessage::ID =>
+                {
+                    let mut chat = play::serverbound::ChatMessage::decode(packet).unwrap();
+                    chat.message = chat.message.to_uppercase();
+                    let injected = play::serverbound::ChatMessage {
+                        message: "injected".into(),
+                    };
+                    inject.push(Direction::Serverbound, injected.encode().unwrap());
+                    let notice = play::clientbound::ChatMessage {
+                        json: r#"{"text":"seen"}"#.into(),
+                        position: ChatPosition::SystemMessage,
+                    };
+                    inject.push(Direction::Clientbound, notice.encode().unwrap());
+                    Some(chat.encode().unwrap())
+                }
+                _ => Some(packet),
+            }
+        };
+        Proxy::new(upstream.to_string()).proxy(stream, hook).await
+    });
+    (addr, task)
+}
+
+async fn handshake(conn: &mut Conn, addr: SocketAddr, next_state: HandshakeState) {
+    conn.set_side(Side::Client);
+    conn.send_packet(handshaking::serverbound::Handshake {
+        protocol_version: PROTOCOL_VERSION,
+        server_address: "localhost".into(),
+        server_port: addr.port(),
+        next_state,
+    })
+    .await
+    .unwrap();
+}
+
+/// The player chats while the server sends a time update and a compressed chat message.
+async fn play_upstream(mut conn: Conn) {
+    conn.send_packet(play::clientbound::TimeUpdate {
+        world_age: 1,
+        time_of_day: 2,
+    })
+    .await
+    .unwrap();
+    conn.send_packet(play::clientbound::ChatMessage {
+        json: format!(r#"{{"text":"{}"}}"#, "a".repeat(500)),
+        position: ChatPosition::ChatBox,
+    })
+    .await
+    .unwrap();
+
+    for expected in ["HELLO", "injected"] {
+        match conn.read_typed::<play::Serverbound>().await.unwrap() {
+            play::Serverbound::ChatMessage(chat) => assert_eq!(chat.message, expected),
+            _ => panic!("expected a chat message"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn login_and_play() {
+    let mut server = Server::new(STATUS);
+    server.compression_threshold = 64;
+    let (upstream_addr, upstream) = upstream(server, play_upstream).await;
+    let seen = Seen::default();
+    let (proxy_addr, proxy) = proxy(upstream_addr, seen.clone()).await;
+
+    let mut conn = Conn::connect(proxy_addr).await.unwrap();
+    handshake(&mut conn, proxy_addr, HandshakeState::Login).await;
+    conn.set_state(ProtocolState::Login);
+    conn.send_packet(login::serverbound::LoginStart { name: "bob".into() })
+        .await
+        .unwrap();
+    loop {
+        match conn.read_typed().await.unwrap() {
+            login::Clientbound::SetCompression(p) => conn.set_compression_threshhold(p.threshold),
+            login::Clientbound::LoginSuccess(p) => {
+                assert_eq!(p.username, "bob");
+                conn.set_state(ProtocolState::Play);
+                break;
+            }
+            _ => panic!("unexpected login packet"),
+        }
+    }
+    assert_eq!(conn.threshold, 64);
+
+    // The time update is dropped by the hook
+    let packet = conn.read_packet().await.unwrap();
+    let chat = play::clientbound::ChatMessage::decode(packet).unwrap();
+    assert_eq!(chat.json.len(), 500 + r#"{"text":""}"#.len());
+
+    conn.send_packet(play::serverbound::ChatMessage {
+        message: "hello".into(),
+    })
+    .await
+    .unwrap();
+    let packet = conn.read_packet().await.unwrap();
+    let notice = play::clientbound::ChatMessage::decode(packet).unwrap();
+    assert_eq!(notice.json, r#"{"text":"seen"}"#);
+
+    upstream.await.unwrap();
+    proxy.await.unwrap().unwrap();
+    assert!(conn.read_packet().await.unwrap_err().is_eof());
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(
+        seen[..4],
+        [
+            (Direction::Serverbound, ProtocolState::Handshaking, 0x00),
+            (Direction::Serverbound, ProtocolState::Login, 0x00),
+            (Direction::Clientbound, ProtocolState::Login, 0x03),
+            (Direction::Clientbound, ProtocolState::Login, 0x02),
+        ]
+    );
+    assert!(seen.contains(&(Direction::Clientbound, ProtocolState::Play, 0x03)));
+}
+
+#[tokio::test]
+async fn status() {
+    let (upstream_addr, upstream) = upstream(Server::new(STATUS), |_| async {}).await;
+    let (proxy_addr, proxy) = proxy(upstream_addr, Seen::default()).await;
+
+    let mut conn = Conn::connect(proxy_addr).await.unwrap();
+    handshake(&mut conn, proxy_addr, HandshakeState::Status).await;
+    conn.set_state(ProtocolState::Status);
+    conn.send_packet(status::serverbound::Request {})
+        .await
+        .unwrap();
+    match conn.read_typed().await.unwrap() {
+        status::Clientbound::Response(p) => assert_eq!(p.response, STATUS),
+        _ => panic!("expected the status"),
+    }
+    conn.send_packet(status::serverbound::Ping { payload: 42 })
+        .await
+        .unwrap();
+    match conn.read_typed().await.unwrap() {
+        status::Clientbound::Pong(p) => assert_eq!(p.payload, 42),
+        _ => panic!("expected a pong"),
+    }
+
+    upstream.await.unwrap();
+    proxy.await.unwrap().unwrap();
+}
+
+/// An offline mode server that still encrypts, so the proxy has to encrypt both legs without a session server.
+#[tokio::test]
+async fn offline_encryption() {
+    use minceraft::net::client::{self, LoginOptions};
+    use rand::Rng;
+    use rsa::{pkcs8::EncodePublicKey, Pkcs1v15Encrypt, RsaPrivateKey};
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let upstream_addr = listener.local_addr().unwrap();
+    let upstream = tokio::spawn(async move {
+        let key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+        let public_key = key.to_public_key().to_public_key_der().unwrap().into_vec();
+        let verify_token = rand::thread_rng().gen::<[u8; 4]>();
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut conn = Conn::try_from(stream).unwrap();
+        conn.set_side(Side::Server);
+        let handshaking::Serverbound::Handshake(_) = conn.read_typed().await.unwrap();
+        conn.set_state(ProtocolState::Login);
+        let login::Serverbound::LoginStart(start) = conn.read_typed().await.unwrap() else {
+            panic!("expected LoginStart");
+        };
+        conn.send_packet(login::clientbound::EncryptionRequest {
+            server_id: String::new(),
+            public_key,
+            verify_token: verify_token.to_vec(),
+        })
+        .await
+        .unwrap();
+        let login::Serverbound::EncryptionResponse(response) = conn.read_typed().await.unwrap()
+        else {
+            panic!("expected EncryptionResponse");
+        };
+        let token = key
+            .decrypt(Pkcs1v15Encrypt, &response.verify_token)
+            .unwrap();
+        assert_eq!(token, verify_token);
+        let shared_secret = key
+            .decrypt(Pkcs1v15Encrypt, &response.shared_secret)
+            .unwrap();
+        conn.enable_encryption(&shared_secret).unwrap();
+
+        conn.send_packet(login::clientbound::LoginSuccess {
+            uuid: "069a79f4-44e9-4726-a5be-fca90e38aaf5".into(),
+            username: start.name,
+        })
+        .await
+        .unwrap();
+        conn.set_state(ProtocolState::Play);
+        play_upstream(conn).await;
+    });
+    let seen = Seen::default();
+    let (proxy_addr, proxy) = proxy(upstream_addr, seen.clone()).await;
+
+    let conn = Conn::connect(proxy_addr).await.unwrap();
+    let (mut conn, success) = client::login_conn(
+        conn,
+        "localhost",
+        proxy_addr.port(),
+        "bob",
+        &LoginOptions::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(success.username, "bob");
+
+    // The time update is dropped by the hook
+    let packet = conn.read_packet().await.unwrap();
+    assert_eq!(packet.id, play::clientbound::ChatMessage::ID);
+    conn.send_packet(play::serverbound::ChatMessage {
+        message: "hello".into(),
+    })
+    .await
+    .unwrap();
+    let packet = conn.read_packet().await.unwrap();
+    let notice = play::clientbound::ChatMessage::decode(packet).unwrap();
+    assert_eq!(notice.json, r#"{"text":"seen"}"#);
+
+    upstream.await.unwrap();
+    proxy.await.unwrap().unwrap();
+    // The hook doesn't see the encryption packets
+    let seen = seen.lock().unwrap();
+    assert!(!seen.contains(&(Direction::Clientbound, ProtocolState::Login, 0x01)));
+    assert!(!seen.contains(&(Direction::Serverbound, ProtocolState::Login, 0x01)));
+}
+
+/// An online mode server behind the proxy, with a session server that lets everyone join.
+#[cfg(feature = "online")]
+#[tokio::test]
+async fn encryption() {
+    use minceraft::net::{
+        client::{self, LoginOptions},
+        server::OnlineMode,
+    };
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let session = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let session_addr = session.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = session.accept().await.unwrap();
+        let mut request = Vec::new();
+        while !request.ends_with(b"\r\n\r\n") {
+            request.push(stream.read_u8().await.unwrap());
+        }
+        let body = r#"{"id":"069a79f444e94726a5befca90e38aaf5","name":"bob","properties":[]}"#;
+        let response = format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+    });
+
+    let mut online_mode = OnlineMode::new().unwrap();
+    online_mode.session_url = format!("http://{}", session_addr);
+    online_mode.http = reqwest::Client::builder().no_proxy().build().unwrap();
+    let mut server = Server::new(STATUS);
+    server.online_mode = Some(online_mode);
+    let (upstream_addr, upstream) = upstream(server, play_upstream).await;
+    let (proxy_addr, proxy) = proxy(upstream_addr, Seen::default()).await;
+
+    // The proxy joins for the client, so the client doesn't talk to the session server
+    let conn = Conn::connect(proxy_addr).await.unwrap();
+    let (mut conn, success) = client::login_conn(
+        conn,
+        "localhost",
+        proxy_addr.port(),
+        "bob",
+        &LoginOptions::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(success.uuid, "069a79f4-44e9-4726-a5be-fca90e38aaf5");
+
+    let packet = conn.read_packet().await.unwrap();
+    assert_eq!(packet.id, play::clientbound::ChatMessage::ID);
+    conn.send_packet(play::serverbound::ChatMessage {
+        message: "hello".into(),
+    })
+    .await
+    .unwrap();
+    let packet = conn.read_packet().await.unwrap();
+    assert_eq!(packet.id, play::clientbound::ChatMessage::ID);
+
+    upstream.await.unwrap();
+    proxy.await.unwrap().unwrap();
+}
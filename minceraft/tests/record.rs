@@ -0,0 +1,105 @@
+use minceraft::net::{
+    conn::Conn,
+    packet::{Direction, ProtocolState, RawPacket},
+    record::{replay, Record, Recorder, Recording, ReplayOptions, MAGIC, VERSION},
+    types::{Encoder, VarInt},
+    Error,
+};
+use std::{io::Cursor, time::Duration};
+
+/// Records a client that reads two packets from the server.
+async fn recording() -> Vec<u8> {
+    let (client, mut server) = Conn::pair();
+    let mut recorder = Recorder::new(client, Vec::new()).await.unwrap();
+    for id in [1, 2] {
+        server
+            .send_raw_packet(RawPacket {
+                id: VarInt(id),
+                data: vec![id as u8; 10],
+            })
+            .await
+            .unwrap();
+        recorder.read_packet().await.unwrap();
+    }
+    recorder.finish().await.unwrap().1
+}
+
+#[tokio::test]
+async fn replay_speed() {
+    let bytes = recording().await;
+
+    for speed in [0.0, -1.0, f64::NAN] {
+        let recording = Recording::new(Cursor::new(bytes.clone())).await.unwrap();
+        match replay(recording, ReplayOptions { speed }) {
+            Err(Error::Invalid(_)) => {}
+            _ => panic!("replayed at speed {}", speed),
+        }
+    }
+
+    let recording = Recording::new(Cursor::new(bytes)).await.unwrap();
+    let options = ReplayOptions {
+        speed: f64::INFINITY,
+    };
+    let mut conn = replay(recording, options).unwrap();
+    for id in [1, 2] {
+        let packet = conn.read_packet().await.unwrap();
+        assert_eq!(packet.id, VarInt(id));
+        assert_eq!(packet.data, [id as u8; 10]);
+    }
+    assert!(conn.read_packet().await.unwrap_err().is_eof());
+}
+
+/// A recording of the client side with `body` as its only record.
+fn recording_with(len: usize, body: &[u8]) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&[VERSION, 0]);
+    VarInt::from(len).write_to(&mut bytes).unwrap();
+    bytes.extend_from_slice(body);
+    bytes
+}
+
+#[tokio::test]
+async fn record_round_trip() {
+    let record = Record {
+        time: Duration::from_micros(1_234_567),
+        direction: Direction::Serverbound,
+        state: ProtocolState::Login,
+        packet: RawPacket {
+            id: VarInt(0x42),
+            data: vec![1, 2, 3],
+        },
+    };
+    let mut body = Vec::new();
+    record.write_to(&mut body).unwrap();
+
+    let bytes = recording_with(body.len(), &body);
+    let mut recording = Recording::new(Cursor::new(bytes)).await.unwrap();
+    let read = recording.next_record().await.unwrap().unwrap();
+    assert_eq!(read.time, record.time);
+    assert_eq!(read.direction, Direction::Serverbound);
+    assert_eq!(read.state, ProtocolState::Login);
+    assert_eq!(read.packet.id, VarInt(0x42));
+    assert_eq!(read.packet.data, [1, 2, 3]);
+    assert!(recording.next_record().await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn truncated_recording() {
+    let mut bytes = recording().await;
+    bytes.truncate(bytes.len() - 3);
+
+    let mut recording = Recording::new(Cursor::new(bytes)).await.unwrap();
+    assert!(recording.next_record().await.unwrap().is_some());
+    match recording.next_record().await {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof),
+        r => panic!("expected an unexpected end of file, got {:?}", r),
+    }
+
+    // A length that is far larger than the file is rejected before anything is read
+    let bytes = recording_with(i32::MAX as usize, &[0; 8]);
+    let mut recording = Recording::new(Cursor::new(bytes)).await.unwrap();
+    assert!(matches!(
+        recording.next_record().await,
+        Err(Error::Invalid(e)) if e.contains("too long")
+    ));
+}
@@ -0,0 +1,93 @@
+use minceraft::net::{
+    status::{split_addr, Chat, StatusResponse, DEFAULT_PORT},
+    Error,
+};
+
+/// The start of the data URI of a favicon.
+const FAVICON_PREFIX: &str = "data:image/png;base64,";
+
+/// The first bytes of every PNG.
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+#[test]
+fn string_description() {
+    let status = StatusResponse::from_json(
+        r#"{"version":{"name":"1.8.9","protocol":47},"players":{"max":20,"online":1,"sample":[{"name":"Notch","id":"069a79f4-44e9-4726-a5be-fca90e38aaf5"}]},"description":"A Minecraft Server"}"#,
+    )
+    .unwrap();
+    assert_eq!(status.version.protocol, 47);
+    assert_eq!(status.players.as_ref().unwrap().sample[0].name, "Notch");
+    assert_eq!(status.description, Chat::from("A Minecraft Server"));
+    assert_eq!(status.favicon_png().unwrap(), None);
+}
+
+#[test]
+fn component_description() {
+    let status = StatusResponse::from_json(
+        r#"{"version":{"name":"1.8.9","protocol":47},"description":{"text":"A ","color":"gold","extra":["Minecraft",{"text":" Server","bold":true}]}}"#,
+    )
+    .unwrap();
+    assert_eq!(status.players, None);
+    let Chat::Component(component) = &status.description else {
+        panic!("expected a component, got {:?}", status.description);
+    };
+    assert_eq!(component.color.as_deref(), Some("gold"));
+    assert_eq!(status.description.to_plain_text(), "A Minecraft Server");
+    assert_eq!(
+        StatusResponse::from_json(&status.to_json()).unwrap(),
+        status
+    );
+}
+
+#[test]
+fn favicon() {
+    let png = [PNG_SIGNATURE, b"rest of the image"].concat();
+    let mut status =
+        StatusResponse::from_json(r#"{"version":{"name":"1.8.9","protocol":47}}"#).unwrap();
+    status.set_favicon_png(&png).unwrap();
+    assert!(status.favicon.as_ref().unwrap().starts_with(FAVICON_PREFIX));
+    assert_eq!(status.favicon_png().unwrap().unwrap(), png);
+
+    // Some servers wrap the base64 in lines
+    let data = base64::encode(&png);
+    let (a, b) = data.split_at(10);
+    status.favicon = Some(format!("{}{}\n{}", FAVICON_PREFIX, a, b));
+    assert_eq!(status.favicon_png().unwrap().unwrap(), png);
+
+    status.favicon = Some(format!("data:image/jpeg;base64,{}", data));
+    assert!(matches!(status.favicon_png(), Err(Error::Invalid(_))));
+    status.favicon = Some(format!("{}{}", FAVICON_PREFIX, base64::encode("not a png")));
+    assert!(matches!(status.favicon_png(), Err(Error::Invalid(_))));
+    assert!(matches!(
+        status.set_favicon_png(b"not a png"),
+        Err(Error::Invalid(_))
+    ));
+}
+
+#[test]
+fn split_addrs() {
+    assert_eq!(
+        split_addr("localhost").unwrap(),
+        ("localhost", DEFAULT_PORT)
+    );
+    assert_eq!(split_addr("localhost:25566").unwrap(), ("localhost", 25566));
+    assert_eq!(split_addr("[::1]:25565").unwrap(), ("::1", 25565));
+    assert_eq!(split_addr("[::1]").unwrap(), ("::1", DEFAULT_PORT));
+    // Without brackets every colon belongs to the address
+    assert_eq!(split_addr("::1").unwrap(), ("::1", DEFAULT_PORT));
+    assert_eq!(
+        split_addr("2001:db8::1").unwrap(),
+        ("2001:db8::1", DEFAULT_PORT)
+    );
+    assert_eq!(
+        split_addr("[2001:db8::1]:25566").unwrap(),
+        ("2001:db8::1", 25566)
+    );
+    for addr in ["localhost:port", "[::1", "[::1]25565"] {
+        assert!(
+            matches!(split_addr(addr), Err(Error::Invalid(_))),
+            "{}",
+            addr
+        );
+    }
+}